@@ -0,0 +1,124 @@
+//! On-disk cache of compiled OpenCL program binaries.
+//!
+//! Compiling the generated kernel takes seconds, which adds up over many short
+//! runs. Binaries are stored under a key derived from everything that affects
+//! the compilation: the source, the device, its driver version and the build
+//! options. A cached binary that fails to load is rebuilt from source.
+
+use alloy_primitives::{hex, keccak256};
+use ocl::{
+    enums::{DeviceInfo, ProgramInfo, ProgramInfoResult},
+    Context, Device, Program,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The default cache directory, following the XDG base directory spec.
+pub(crate) fn default_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|cache| cache.join("createxcrunch"))
+}
+
+/// Builds `src` for `device`, loading the binary from `dir` if it has been
+/// built before and storing it there otherwise. Problems with the cache are
+/// never fatal, but handed to `notice`.
+pub(crate) fn build_program(
+    context: &Context,
+    device: Device,
+    src: &str,
+    options: &str,
+    dir: Option<&Path>,
+    notice: &mut dyn FnMut(&str),
+) -> ocl::Result<Program> {
+    let Some(dir) = dir else {
+        return build_from_source(context, device, src, options);
+    };
+
+    let name = device.name()?;
+    let driver_version = device.info(DeviceInfo::DriverVersion)?.to_string();
+    let key = cache_key(&name, &driver_version, src, options);
+
+    if let Some(binary) = load(dir, &key) {
+        match Program::builder()
+            .devices(device)
+            .binaries(&[&binary[..]])
+            .cmplr_opt(options)
+            .build(context)
+        {
+            Ok(program) => return Ok(program),
+            Err(_) => notice(&format!(
+                "Cached kernel {} is unusable, rebuilding...",
+                path(dir, &key).display()
+            )),
+        }
+    }
+
+    let program = build_from_source(context, device, src, options)?;
+
+    if let Err(e) = binary(&program).and_then(|binary| Ok(store(dir, &key, &binary)?)) {
+        notice(&format!(
+            "Could not cache the kernel in {}: {}",
+            dir.display(),
+            e
+        ));
+    }
+
+    Ok(program)
+}
+
+fn build_from_source(
+    context: &Context,
+    device: Device,
+    src: &str,
+    options: &str,
+) -> ocl::Result<Program> {
+    Program::builder()
+        .devices(device)
+        .src(src)
+        .cmplr_opt(options)
+        .build(context)
+}
+
+/// Hashes everything that determines the compiled binary: the source, the
+/// name and driver version of the device, and the build options.
+pub fn cache_key(device_name: &str, driver_version: &str, src: &str, options: &str) -> String {
+    let key = [src, device_name, driver_version, options].join("\0");
+
+    hex::encode(keccak256(key))
+}
+
+fn path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.bin", key))
+}
+
+/// The binary cached in `dir` under `key`, if any.
+pub fn load(dir: &Path, key: &str) -> Option<Vec<u8>> {
+    fs::read(path(dir, key)).ok()
+}
+
+/// Caches a binary in `dir` under `key`, creating the directory if needed.
+pub fn store(dir: &Path, key: &str, binary: &[u8]) -> io::Result<()> {
+    let path = path(dir, key);
+
+    // write to a temporary file first, so that concurrent runs never see a
+    // partially written binary
+    fs::create_dir_all(dir)?;
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, binary)?;
+    fs::rename(&tmp, &path)?;
+
+    Ok(())
+}
+
+fn binary(program: &Program) -> ocl::Result<Vec<u8>> {
+    match program.info(ProgramInfo::Binaries)? {
+        ProgramInfoResult::Binaries(binaries) if binaries.len() == 1 => {
+            Ok(binaries.into_iter().next().unwrap())
+        }
+        _ => Err("expected a single program binary".into()),
+    }
+}
@@ -0,0 +1,286 @@
+//! ABI-encoded CreateX calls that deploy a contract with a found salt.
+
+use crate::{CreateXVariant, MAX_INIT_CODE_HASHES};
+use alloy_primitives::{hex, keccak256, Address, U256};
+use std::str::FromStr;
+
+// creation code of the EIP-1167 minimal proxy deployed by `deployCreate2Clone`,
+// with the implementation address in between
+const CLONE_PREFIX: [u8; 20] = hex!("3d602d80600a3d3981f3363d3d373d3d3d363d73");
+const CLONE_SUFFIX: [u8; 15] = hex!("5af43d82803e903d91602b57fd5bf3");
+
+/// How a contract is deployed through CreateX.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Deployment {
+    /// `deployCreate2(bytes32,bytes)` or `deployCreate3(bytes32,bytes)`.
+    InitCode { init_code: Vec<u8> },
+    /// `deployCreate2AndInit` or `deployCreate3AndInit`, which call the new
+    /// contract with `data` right after deploying it.
+    InitCodeAndInit {
+        init_code: Vec<u8>,
+        data: Vec<u8>,
+        constructor_amount: U256,
+        init_call_amount: U256,
+    },
+    /// `deployCreate2Clone(bytes32,address,bytes)`, which deploys a minimal
+    /// proxy to `implementation` and calls it with `data`.
+    Clone {
+        implementation: [u8; 20],
+        data: Vec<u8>,
+        init_call_amount: U256,
+    },
+}
+
+impl Deployment {
+    /// Builds the deployment from the command line arguments: the init code
+    /// in hex or `@<path>` to a file holding it, or the clone implementation,
+    /// plus the optional init call and the amounts sent along.
+    pub fn from_args(
+        init_code: Option<&str>,
+        clone: Option<&str>,
+        init_call: Option<&str>,
+        constructor_amount: Option<&str>,
+        init_call_amount: Option<&str>,
+    ) -> Result<Self, String> {
+        let data = init_call
+            .map(|data| decode(data, "init call"))
+            .transpose()?;
+        let constructor_amount = amount(constructor_amount, "constructor amount")?;
+        let init_call_amount = amount(init_call_amount, "init call amount")?;
+
+        match (init_code, clone) {
+            (Some(init_code), None) => {
+                let init_code = read_init_code(init_code)?;
+
+                match data {
+                    None if constructor_amount.is_zero() && init_call_amount.is_zero() => {
+                        Ok(Self::InitCode { init_code })
+                    }
+                    data => Ok(Self::InitCodeAndInit {
+                        init_code,
+                        data: data.unwrap_or_default(),
+                        constructor_amount,
+                        init_call_amount,
+                    }),
+                }
+            }
+            (None, Some(implementation)) => {
+                let implementation = decode(implementation, "clone implementation")?
+                    .try_into()
+                    .map_err(|_| "invalid length for clone implementation address")?;
+                if !constructor_amount.is_zero() {
+                    return Err("clones have no constructor to send an amount to".to_string());
+                }
+
+                Ok(Self::Clone {
+                    implementation,
+                    data: data.unwrap_or_default(),
+                    init_call_amount,
+                })
+            }
+            _ => Err("exactly one of the init code or the clone implementation is required".into()),
+        }
+    }
+
+    /// Hash of the code CreateX passes to CREATE2 (or the CREATE3 proxy).
+    pub fn init_code_hash(&self) -> [u8; 32] {
+        match self {
+            Self::InitCode { init_code } | Self::InitCodeAndInit { init_code, .. } => {
+                keccak256(init_code).0
+            }
+            Self::Clone { implementation, .. } => {
+                keccak256([&CLONE_PREFIX[..], implementation, &CLONE_SUFFIX].concat()).0
+            }
+        }
+    }
+
+    /// The Solidity signature of the CreateX function to call.
+    pub fn signature(&self, create_variant: &CreateXVariant) -> &'static str {
+        match (self, create_variant) {
+            (Self::InitCode { .. }, CreateXVariant::Create2 { .. }) => {
+                "deployCreate2(bytes32,bytes)"
+            }
+            (Self::InitCode { .. }, CreateXVariant::Create3) => "deployCreate3(bytes32,bytes)",
+            (Self::InitCodeAndInit { .. }, CreateXVariant::Create2 { .. }) => {
+                "deployCreate2AndInit(bytes32,bytes,bytes,(uint256,uint256))"
+            }
+            (Self::InitCodeAndInit { .. }, CreateXVariant::Create3) => {
+                "deployCreate3AndInit(bytes32,bytes,bytes,(uint256,uint256))"
+            }
+            (Self::Clone { .. }, _) => "deployCreate2Clone(bytes32,address,bytes)",
+        }
+    }
+
+    /// The value to send along with the call.
+    pub fn value(&self) -> U256 {
+        match self {
+            Self::InitCode { .. } => U256::ZERO,
+            Self::InitCodeAndInit {
+                constructor_amount,
+                init_call_amount,
+                ..
+            } => constructor_amount + init_call_amount,
+            Self::Clone {
+                init_call_amount, ..
+            } => *init_call_amount,
+        }
+    }
+
+    /// ABI-encodes the call deploying the contract with `salt`.
+    pub fn calldata(&self, create_variant: &CreateXVariant, salt: [u8; 32]) -> Vec<u8> {
+        let selector = &keccak256(self.signature(create_variant))[..4];
+
+        let args = match self {
+            Self::InitCode { init_code } => vec![Arg::Static(salt), Arg::Dynamic(init_code)],
+            Self::InitCodeAndInit {
+                init_code,
+                data,
+                constructor_amount,
+                init_call_amount,
+            } => vec![
+                Arg::Static(salt),
+                Arg::Dynamic(init_code),
+                Arg::Dynamic(data),
+                // the `Values` tuple is static, so it is encoded in place
+                Arg::Static(constructor_amount.to_be_bytes()),
+                Arg::Static(init_call_amount.to_be_bytes()),
+            ],
+            Self::Clone {
+                implementation,
+                data,
+                ..
+            } => vec![
+                Arg::Static(salt),
+                Arg::Static(Address::from(*implementation).into_word().0),
+                Arg::Dynamic(data),
+            ],
+        };
+
+        // dynamic arguments are appended after the head, which holds their
+        // offsets instead
+        let mut head = selector.to_vec();
+        let mut tail = Vec::new();
+        for arg in &args {
+            match arg {
+                Arg::Static(word) => head.extend_from_slice(word),
+                Arg::Dynamic(bytes) => {
+                    head.extend(to_word(32 * args.len() + tail.len()));
+                    tail.extend(to_word(bytes.len()));
+                    tail.extend_from_slice(bytes);
+                    tail.resize(tail.len().next_multiple_of(32), 0);
+                }
+            }
+        }
+
+        head.extend(tail);
+        head
+    }
+
+    /// An equivalent `cast send` command line.
+    pub fn cast_send(
+        &self,
+        factory: [u8; 20],
+        create_variant: &CreateXVariant,
+        salt: [u8; 32],
+    ) -> String {
+        let mut args = vec![hex::encode_prefixed(salt)];
+        match self {
+            Self::InitCode { init_code } => args.push(hex::encode_prefixed(init_code)),
+            Self::InitCodeAndInit {
+                init_code,
+                data,
+                constructor_amount,
+                init_call_amount,
+            } => {
+                args.push(hex::encode_prefixed(init_code));
+                args.push(hex::encode_prefixed(data));
+                args.push(format!("\"({},{})\"", constructor_amount, init_call_amount));
+            }
+            Self::Clone {
+                implementation,
+                data,
+                ..
+            } => {
+                args.push(Address::from(*implementation).to_checksum(None));
+                args.push(hex::encode_prefixed(data));
+            }
+        }
+
+        let mut command = format!(
+            "cast send {} \"{}\" {}",
+            Address::from(factory).to_checksum(None),
+            self.signature(create_variant),
+            args.join(" ")
+        );
+        if !self.value().is_zero() {
+            command.push_str(&format!(" --value {}", self.value()));
+        }
+        command
+    }
+}
+
+/// Checks the deployments against the variant being mined for. Each one must
+/// match one of the CREATE2 init code hashes, while a CREATE3 address does not
+/// depend on the init code, so only one deployment can be given.
+pub(crate) fn validate(
+    deployments: &[Deployment],
+    create_variant: &CreateXVariant,
+) -> Result<(), &'static str> {
+    match create_variant {
+        CreateXVariant::Create2 { init_code_hashes } => {
+            if deployments.len() > MAX_INIT_CODE_HASHES {
+                return Err("at most 16 init codes can be given");
+            }
+            for deployment in deployments {
+                if !init_code_hashes.contains(&deployment.init_code_hash()) {
+                    return Err("init code does not match any of the init code hashes");
+                }
+            }
+        }
+        CreateXVariant::Create3 => {
+            if deployments.len() > 1 {
+                return Err("only one init code can be given for CREATE3");
+            }
+            if let Some(Deployment::Clone { .. }) = deployments.first() {
+                return Err("CreateX cannot deploy clones with CREATE3");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads init code given in hex, or as `@<path>` to a file holding it in hex.
+pub fn read_init_code(arg: &str) -> Result<Vec<u8>, String> {
+    let init_code = match arg.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read init code from {}: {}", path, e))?,
+        None => arg.to_string(),
+    };
+    let init_code = decode(init_code.trim(), "init code")?;
+    if init_code.is_empty() {
+        return Err("init code must not be empty".to_string());
+    }
+
+    Ok(init_code)
+}
+
+pub(crate) fn decode(s: &str, what: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s).map_err(|e| format!("could not decode {}: {}", what, e))
+}
+
+pub(crate) fn amount(s: Option<&str>, what: &str) -> Result<U256, String> {
+    s.map_or(Ok(U256::ZERO), |s| {
+        U256::from_str(s).map_err(|e| format!("could not parse {}: {}", what, e))
+    })
+}
+
+/// An argument of a CreateX function, as far as the ABI encoding goes.
+enum Arg<'a> {
+    Static([u8; 32]),
+    Dynamic(&'a [u8]),
+}
+
+fn to_word(n: usize) -> [u8; 32] {
+    U256::from(n).to_be_bytes()
+}
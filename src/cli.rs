@@ -1,4 +1,6 @@
+use crate::{output::OutputFormat, progress::Progress, results::SortKey, MAX_LOOP_COUNT};
 use clap::{command, ArgAction, ArgGroup, Args, Parser, Subcommand};
+use std::net::SocketAddr;
 
 #[derive(Parser)]
 #[command(arg_required_else_help = true)]
@@ -25,6 +27,7 @@ fn to_lowercase_boxed_str(s: &str) -> Result<Box<str>, &'static str> {
 #[derive(Args)]
 #[clap(group = ArgGroup::new("search-criteria").multiple(true).required(true))]
 #[clap(group = ArgGroup::new("zeros-threshold"))]
+#[clap(group = ArgGroup::new("deployment"))]
 pub struct CliArgs {
     #[arg(
         id = "factory",
@@ -107,6 +110,61 @@ pub struct CliArgs {
     )]
     pub pattern: Option<Box<str>>,
 
+    #[arg(
+        id = "loop-count",
+        long,
+        default_value = "1",
+        long_help = "Number of candidates each GPU work item tries per kernel dispatch. Higher values amortize the per-candidate setup and reduce host round trips, at the cost of longer dispatches.\n\nExample: --loop-count 16.",
+        help_heading = "Crunching options",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_LOOP_COUNT as i64)
+    )]
+    pub loop_count: u32,
+
+    #[arg(
+        id = "generic-kernel",
+        long,
+        long_help = "Use a kernel that reads the search parameters from a buffer instead of having them compiled in. It is slower than the default, specialised kernel, but the same build works for any parameters.",
+        action = ArgAction::SetTrue,
+        help_heading = "Crunching options"
+    )]
+    pub generic_kernel: bool,
+
+    #[arg(
+        id = "kernel-cache",
+        long,
+        long_help = "Directory in which compiled kernels are cached, so that later runs with the same parameters on the same device skip the compilation. Defaults to createxcrunch in the user's cache directory.",
+        help_heading = "Crunching options",
+        conflicts_with = "no-kernel-cache"
+    )]
+    pub kernel_cache: Option<String>,
+
+    #[arg(
+        id = "no-kernel-cache",
+        long,
+        long_help = "Always compile the kernel from source, without reading or writing the kernel cache.",
+        action = ArgAction::SetTrue,
+        help_heading = "Crunching options"
+    )]
+    pub no_kernel_cache: bool,
+
+    #[arg(
+        id = "skip-self-test",
+        long,
+        long_help = "Start mining without first checking the kernel against the CPU reference for a few known-answer nonces. For a specialised kernel, the check compiles a copy of it with thresholds every address meets, which roughly doubles the time to start unless that copy is in the kernel cache. The generic kernel is checked as built, without a second compile.",
+        action = ArgAction::SetTrue,
+        help_heading = "Crunching options"
+    )]
+    pub skip_self_test: bool,
+
+    #[arg(
+        id = "continue-on-mismatch",
+        long,
+        long_help = "Keep mining when the CPU reference disagrees with a solution reported by the GPU. Such solutions are flagged and never written to the output file.",
+        action = ArgAction::SetTrue,
+        help_heading = "Crunching options"
+    )]
+    pub continue_on_mismatch: bool,
+
     #[arg(
         id = "output",
         long,
@@ -116,6 +174,157 @@ pub struct CliArgs {
         help_heading = "Output options"
     )]
     pub output: String,
+
+    #[arg(
+        id = "format",
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        long_help = "Format of the records written to the output file. The jsonl and csv formats include the guarded salt, checksummed address, zero byte counts, search criterion, deployment settings, timestamp, device and difficulty of each result.",
+        help_heading = "Output options"
+    )]
+    pub format: OutputFormat,
+
+    #[arg(
+        id = "init-code",
+        long,
+        value_name = "HEX|@PATH",
+        long_help = "Init code of the contract, in hex or as @<path> to a file holding it in hex. Each found salt is then written along with the ABI-encoded calldata of the CreateX call deploying the contract, and an equivalent `cast send` command line. For CREATE2, pass one init code per init code hash, comma-separated.\n\nExample: --init-code @out/Token.bin.",
+        help_heading = "Deployment options",
+        num_args = 1..,
+        value_delimiter = ',',
+        conflicts_with = "clone",
+        group = "deployment"
+    )]
+    pub init_code: Vec<String>,
+
+    #[arg(
+        id = "clone",
+        long,
+        value_name = "ADDRESS",
+        long_help = "Deploy an EIP-1167 minimal proxy to the given implementation with deployCreate2Clone, instead of passing the init code. The init code hash must be that of the proxy. Only available for CREATE2.",
+        help_heading = "Deployment options",
+        group = "deployment"
+    )]
+    pub clone: Option<String>,
+
+    #[arg(
+        id = "init-call",
+        long,
+        value_name = "HEX",
+        long_help = "Calldata to call the contract with right after deploying it, using deployCreate2AndInit or deployCreate3AndInit. For clones, the call to the proxy.",
+        help_heading = "Deployment options",
+        requires = "deployment"
+    )]
+    pub init_call: Option<String>,
+
+    #[arg(
+        id = "constructor-amount",
+        long,
+        value_name = "WEI",
+        long_help = "Amount of wei sent to the constructor, using deployCreate2AndInit or deployCreate3AndInit.",
+        help_heading = "Deployment options",
+        requires = "init-code"
+    )]
+    pub constructor_amount: Option<String>,
+
+    #[arg(
+        id = "init-call-amount",
+        long,
+        value_name = "WEI",
+        long_help = "Amount of wei sent along with the init call.",
+        help_heading = "Deployment options",
+        requires = "init-call"
+    )]
+    pub init_call_amount: Option<String>,
+
+    #[cfg(feature = "sqlite")]
+    #[arg(
+        id = "database",
+        long,
+        value_name = "PATH",
+        long_help = "SQLite database to also store every found salt in, along with the settings it was mined for. The database is created if needed, and can be shared by several runs.",
+        help_heading = "Output options"
+    )]
+    pub database: Option<String>,
+
+    #[arg(
+        id = "on-found",
+        long,
+        value_name = "COMMAND",
+        long_help = "Command to run through the shell for every found salt, e.g. to send a notification. It gets the solution as a JSON object on its standard input, and the salt, address, zero byte counts, criterion and init code hash in the CREATEXCRUNCH_SALT, CREATEXCRUNCH_ADDRESS, CREATEXCRUNCH_CHECKSUM_ADDRESS, CREATEXCRUNCH_LEADING_ZEROS, CREATEXCRUNCH_TOTAL_ZEROS, CREATEXCRUNCH_CRITERION and CREATEXCRUNCH_INIT_CODE_HASH environment variables. Commands run one at a time in the background, so mining goes on while they run. Failures are logged to standard error.\n\nExample: --on-found 'notify-send \"Found $CREATEXCRUNCH_ADDRESS\"'.",
+        help_heading = "Output options"
+    )]
+    pub on_found: Option<String>,
+
+    #[arg(
+        id = "on-found-timeout",
+        long,
+        value_name = "SECONDS",
+        default_value = "30",
+        requires = "on-found",
+        long_help = "Time after which a command run with --on-found is killed.",
+        help_heading = "Output options"
+    )]
+    pub on_found_timeout: u64,
+
+    #[arg(
+        id = "progress",
+        long,
+        value_enum,
+        default_value_t = Progress::Auto,
+        long_help = "How progress is reported. The tui mode shows a full-screen dashboard with the rate of each device averaged over the last 30 seconds, a throughput history, the best salt so far, the expected time to the next one and a scrollable list of found salts. On the dashboard, p pauses and resumes mining, + and - raise and lower the thresholds, s changes the sort order of the list and q stops. The screen mode is the simpler status screen redrawn every second. The plain and json modes instead print a single-line status record every --status-interval seconds and a line per found salt, so that logs stay parseable. The auto mode uses the dashboard on a terminal and plain lines otherwise.",
+        help_heading = "Output options"
+    )]
+    pub progress: Progress,
+
+    #[arg(
+        id = "quiet",
+        long,
+        short,
+        action = ArgAction::SetTrue,
+        conflicts_with = "progress",
+        long_help = "Shorthand for --progress plain.",
+        help_heading = "Output options"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        id = "status-interval",
+        long,
+        value_name = "SECONDS",
+        default_value = "10",
+        long_help = "Time between status records in the plain and json progress modes.",
+        help_heading = "Output options"
+    )]
+    pub status_interval: u64,
+
+    #[arg(
+        id = "metrics-addr",
+        long,
+        value_name = "ADDRESS",
+        long_help = "Serve Prometheus metrics on http://<ADDRESS>/metrics while mining: the candidates tried, the current rate, the salts found by criterion and the duration of kernel dispatches, labelled with the device, CREATE variant, factory and caller.\n\nExample: --metrics-addr 0.0.0.0:9100.",
+        help_heading = "Output options"
+    )]
+    pub metrics_addr: Option<SocketAddr>,
+
+    #[arg(
+        id = "emit-kernel",
+        long,
+        value_name = "PATH",
+        long_help = "Write the generated OpenCL source of the kernel to the given file.",
+        help_heading = "Debugging options"
+    )]
+    pub emit_kernel: Option<String>,
+
+    #[arg(
+        id = "dry-run",
+        long,
+        long_help = "Print the resolved configuration and salt layout, and exit without mining. Does not require a GPU.",
+        action = ArgAction::SetTrue,
+        help_heading = "Debugging options"
+    )]
+    pub dry_run: bool,
 }
 
 #[derive(Args)]
@@ -126,12 +335,471 @@ pub struct Create2Args {
     #[arg(
         long = "code-hash",
         visible_alias = "ch",
-        long_help = "Set the init code hash in hex format.",
+        long_help = "Set the init code hash in hex format. Pass several comma-separated hashes to mine for a family of contracts at once, in which case each found salt is reported along with the init code hash it belongs to.\n\nExample: --code-hash 0x1f...a0,0x7c...3e.",
         help_heading = "Crunching options",
         required = true,
+        num_args = 1..,
+        value_delimiter = ',',
         visible_alias = "ch"
     )]
-    pub init_code_hash: String,
+    pub init_code_hashes: Vec<String>,
+}
+
+#[derive(Args)]
+#[clap(group = ArgGroup::new("init-code-source").required(true))]
+pub struct FoundryArgs {
+    #[arg(
+        value_name = "RECORDS",
+        long_help = "Output file in the jsonl or csv format holding the found salt. Plain text output lacks the deployment settings the script needs."
+    )]
+    pub records: String,
+
+    #[arg(
+        id = "salt",
+        long,
+        short,
+        long_help = "Salt of the record to export. Can be omitted if the file holds a single record."
+    )]
+    pub salt: Option<String>,
+
+    #[arg(
+        id = "artifact",
+        long,
+        group = "init-code-source",
+        long_help = "Foundry artifact whose creation code is deployed, as passed to vm.getCode.\n\nExample: --artifact Token.sol:Token."
+    )]
+    pub artifact: Option<String>,
+
+    #[arg(
+        id = "constructor-args",
+        long,
+        value_name = "HEX",
+        requires = "artifact",
+        long_help = "ABI-encoded constructor arguments, appended to the creation code of the artifact."
+    )]
+    pub constructor_args: Option<String>,
+
+    #[arg(
+        id = "init-code",
+        long,
+        value_name = "HEX|@PATH",
+        group = "init-code-source",
+        long_help = "Init code to deploy, in hex or as @<path> to a file holding it in hex."
+    )]
+    pub init_code: Option<String>,
+
+    #[arg(
+        id = "init-call",
+        long,
+        value_name = "HEX",
+        long_help = "Calldata to call the contract with right after deploying it, using deployCreate2AndInit or deployCreate3AndInit."
+    )]
+    pub init_call: Option<String>,
+
+    #[arg(
+        id = "constructor-amount",
+        long,
+        value_name = "WEI",
+        requires = "init-call",
+        long_help = "Amount of wei sent to the constructor."
+    )]
+    pub constructor_amount: Option<String>,
+
+    #[arg(
+        id = "init-call-amount",
+        long,
+        value_name = "WEI",
+        requires = "init-call",
+        long_help = "Amount of wei sent along with the init call."
+    )]
+    pub init_call_amount: Option<String>,
+
+    #[arg(
+        id = "contract-name",
+        long,
+        default_value = "Deploy",
+        long_help = "Name of the script contract."
+    )]
+    pub contract_name: String,
+
+    #[arg(
+        id = "output",
+        long,
+        short,
+        default_value = "Deploy.s.sol",
+        long_help = "File to write the script to."
+    )]
+    pub output: String,
+}
+
+#[derive(Args)]
+#[clap(group = ArgGroup::new("search-criteria").multiple(true).required(true))]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct ResultsArgs {
+    #[cfg(feature = "sqlite")]
+    #[command(subcommand)]
+    pub command: Option<ResultsCommands>,
+
+    #[arg(
+        value_name = "FILES",
+        required = true,
+        long_help = "Output files to read, in any of the output formats."
+    )]
+    pub files: Vec<String>,
+
+    #[arg(
+        id = "factory",
+        long,
+        short,
+        default_value = "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+        long_help = "Set the factory address.",
+        help_heading = "Job options"
+    )]
+    pub factory: String,
+
+    #[arg(
+        id = "caller",
+        long,
+        short,
+        long_help = "Set the caller address in hex format for a permissioned deployment.",
+        help_heading = "Job options"
+    )]
+    pub caller: Option<String>,
+
+    #[arg(
+        id = "chain-id",
+        long = "crosschain",
+        short = 'x',
+        long_help = "Set whether or not to enable crosschain deployment protection.",
+        help_heading = "Job options",
+        visible_alias = "crp"
+    )]
+    pub chain_id: Option<u64>,
+
+    #[arg(
+        long = "code-hash",
+        visible_alias = "ch",
+        long_help = "Init code hashes the salts were mined for, comma-separated. Without them, the salts are checked as CREATE3 salts.",
+        help_heading = "Job options",
+        num_args = 1..,
+        value_delimiter = ','
+    )]
+    pub init_code_hashes: Option<Vec<String>>,
+
+    #[arg(
+        id = "zeros",
+        long = "leading",
+        short = 'z',
+        group = "search-criteria",
+        long_help = "Minimum number of leading zero bytes.",
+        help_heading = "Job options"
+    )]
+    pub zeros: Option<u8>,
+
+    #[arg(
+        id = "total",
+        long = "total",
+        short = 't',
+        group = "search-criteria",
+        long_help = "Total number of zero bytes. If used in conjunction with --leading, search criteria will be both thresholds. Pass --either to search for either threshold.",
+        help_heading = "Job options"
+    )]
+    pub total: Option<u8>,
+
+    #[arg(
+        id = "either",
+        long = "either",
+        long_help = "Search for either threshold. Must be used with --leading and --total.",
+        requires_all = &["zeros", "total"],
+        action = ArgAction::SetTrue,
+        help_heading = "Job options"
+    )]
+    pub either: bool,
+
+    #[arg(
+        id = "pattern",
+        long = "matching",
+        short = 'm',
+        group = "search-criteria",
+        long_help = "Matching pattern for the contract address.",
+        help_heading = "Job options",
+        conflicts_with_all = &["zeros", "total"],
+        value_parser = to_lowercase_boxed_str
+    )]
+    pub pattern: Option<Box<str>>,
+
+    #[arg(
+        id = "sort",
+        long,
+        value_enum,
+        default_value_t = SortKey::Leading,
+        long_help = "Score to rank the entries by. Ranking by pattern requires --matching.",
+        help_heading = "Output options"
+    )]
+    pub sort: SortKey,
+
+    #[arg(
+        id = "top",
+        long,
+        short = 'n',
+        long_help = "Only keep the best N entries.",
+        help_heading = "Output options"
+    )]
+    pub top: Option<usize>,
+
+    #[arg(
+        id = "output",
+        long,
+        short,
+        long_help = "Write the verified entries to this file instead of printing them.",
+        help_heading = "Output options"
+    )]
+    pub output: Option<String>,
+
+    #[arg(
+        id = "format",
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        long_help = "Format of the file written with --output.",
+        help_heading = "Output options"
+    )]
+    pub format: OutputFormat,
+
+    #[cfg(feature = "sqlite")]
+    #[arg(
+        id = "database",
+        long,
+        value_name = "PATH",
+        long_help = "SQLite database to store the verified entries in, to import the results of past runs.",
+        help_heading = "Output options"
+    )]
+    pub database: Option<String>,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Args)]
+pub struct QueryArgs {
+    #[arg(
+        id = "database",
+        long,
+        value_name = "PATH",
+        required = true,
+        long_help = "SQLite database to search."
+    )]
+    pub database: String,
+
+    #[arg(
+        id = "variant",
+        long,
+        value_parser = ["create2", "create3"],
+        long_help = "Only show salts for this deployment variant."
+    )]
+    pub create_variant: Option<String>,
+
+    #[arg(
+        id = "factory",
+        long,
+        short,
+        long_help = "Only show salts for this factory."
+    )]
+    pub factory: Option<String>,
+
+    #[arg(
+        id = "caller",
+        long,
+        short,
+        long_help = "Only show salts permissioned for this caller."
+    )]
+    pub caller: Option<String>,
+
+    #[arg(
+        id = "chain-id",
+        long = "crosschain",
+        short = 'x',
+        long_help = "Only show salts protected for this chain.",
+        visible_alias = "crp"
+    )]
+    pub chain_id: Option<u64>,
+
+    #[arg(
+        id = "code-hash",
+        long = "code-hash",
+        visible_alias = "ch",
+        long_help = "Only show salts for this init code hash."
+    )]
+    pub init_code_hash: Option<String>,
+
+    #[arg(
+        id = "zeros",
+        long = "leading",
+        short = 'z',
+        long_help = "Only show addresses with at least this many leading zero bytes."
+    )]
+    pub zeros: Option<u8>,
+
+    #[arg(
+        id = "total",
+        long = "total",
+        short = 't',
+        long_help = "Only show addresses with at least this many zero bytes."
+    )]
+    pub total: Option<u8>,
+
+    #[arg(
+        id = "top",
+        long,
+        short = 'n',
+        long_help = "Only show the best N salts."
+    )]
+    pub top: Option<usize>,
+
+    #[arg(
+        id = "format",
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        long_help = "Format to print the salts in."
+    )]
+    pub format: OutputFormat,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+pub enum ResultsCommands {
+    #[command(about = "Search the salts stored in a database.")]
+    Query(QueryArgs),
+}
+
+#[derive(Args)]
+pub struct EstimateArgs {
+    #[arg(
+        id = "rate",
+        long,
+        value_name = "MILLIONS",
+        long_help = "Rate to estimate with, in millions of candidates per second, e.g. as shown while mining. Without it, the kernel for the search is benchmarked on the GPU.\n\nExample: --rate 850."
+    )]
+    pub rate: Option<f64>,
+
+    #[arg(
+        id = "benchmark",
+        long,
+        value_name = "SECONDS",
+        default_value = "5",
+        conflicts_with = "rate",
+        long_help = "How long to benchmark the kernel for."
+    )]
+    pub benchmark: u64,
+
+    #[command(subcommand)]
+    pub search: EstimateCommands,
+}
+
+#[derive(Subcommand)]
+pub enum EstimateCommands {
+    #[command(about = "Estimate a search for a CREATE3 deployment address.")]
+    Create3(CliArgs),
+    #[command(about = "Estimate a search for a CREATE2 deployment address.")]
+    Create2(Create2Args),
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[arg(
+        value_name = "JOBFILE",
+        long_help = "TOML or JSON file listing the jobs, each with the settings of the create2 and create3 commands, an output file and a stop condition (max_solutions or max_time in seconds). Files ending in .json are read as JSON. Every job is checked before the first one runs.\n\nExample:\n\nschedule = \"round-robin\"  # or \"fair\", or \"in-order\", the default\nslice = 300                # seconds per turn when round-robin\n\n[[jobs]]\nname = \"token\"\ninit_code_hashes = [\"0x...\"]\ncaller = \"0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A\"\ncrosschain = 1\nleading = 4\nmax_solutions = 1\noutput = \"token.txt\"\nweight = 2                 # share of the device when fair"
+    )]
+    pub jobfile: String,
+
+    #[arg(
+        id = "gpu-device-id",
+        long,
+        short,
+        default_value = "0",
+        long_help = "Set the GPU device ID."
+    )]
+    pub gpu_device_id: u8,
+
+    #[arg(
+        id = "cpu",
+        long,
+        conflicts_with = "gpu-device-id",
+        long_help = "Run the jobs on the CPU instead of a GPU. This is orders of magnitude slower, and meant for testing."
+    )]
+    pub cpu: bool,
+
+    #[arg(
+        id = "progress",
+        long,
+        value_enum,
+        default_value_t = Progress::Auto,
+        long_help = "How the progress of each job is reported, as for the create2 and create3 commands."
+    )]
+    pub progress: Progress,
+
+    #[arg(
+        id = "quiet",
+        long,
+        short,
+        action = ArgAction::SetTrue,
+        conflicts_with = "progress",
+        long_help = "Shorthand for --progress plain."
+    )]
+    pub quiet: bool,
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    #[arg(
+        id = "listen",
+        long,
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:8080",
+        long_help = "Address to serve the job API on. The API has no authentication, so only expose it to trusted clients."
+    )]
+    pub listen: SocketAddr,
+
+    #[arg(
+        id = "devices",
+        long,
+        value_name = "IDS",
+        value_delimiter = ',',
+        default_value = "0",
+        long_help = "GPU device IDs to run jobs on, up to --concurrent jobs per device.\n\nExample: --devices 0,1."
+    )]
+    pub devices: Vec<u8>,
+
+    #[arg(
+        id = "cpu",
+        long,
+        conflicts_with = "devices",
+        long_help = "Run jobs on the CPU instead of a GPU. This is orders of magnitude slower, and meant for testing."
+    )]
+    pub cpu: bool,
+
+    #[arg(
+        id = "concurrent",
+        long,
+        value_name = "JOBS",
+        default_value = "1",
+        long_help = "Number of jobs each device mines at once. The jobs take turns by kernel dispatch, each getting a share of the device in proportion to its weight, so that a long job does not hold up the ones queued after it."
+    )]
+    pub concurrent: usize,
+
+    #[arg(
+        id = "output",
+        long,
+        short,
+        value_name = "PATH",
+        long_help = "File to also append the solutions of every job to, as JSON lines."
+    )]
+    pub output: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum ExportCommands {
+    #[command(about = "Generate a Foundry script deploying with a found salt.")]
+    Foundry(FoundryArgs),
 }
 
 #[derive(Subcommand)]
@@ -140,4 +808,14 @@ pub enum Commands {
     Create3(CliArgs),
     #[command(about = "Mine for a CREATE2 deployment address.")]
     Create2(Create2Args),
+    #[command(subcommand, about = "Export a found salt for deployment.")]
+    Export(ExportCommands),
+    #[command(about = "Dedupe, verify and rank the salts in output files.")]
+    Results(ResultsArgs),
+    #[command(about = "Estimate how long a search takes, without mining.")]
+    Estimate(EstimateArgs),
+    #[command(about = "Mine for the jobs listed in a job file.")]
+    Run(RunArgs),
+    #[command(about = "Serve a local HTTP API queueing mining jobs onto the devices.")]
+    Serve(ServeArgs),
 }
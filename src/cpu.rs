@@ -0,0 +1,134 @@
+//! Mining on the CPU with the reference implementation.
+//!
+//! This is orders of magnitude slower than the kernel, but needs no OpenCL
+//! device, which makes it useful for tests and for checking a setup end to end.
+
+use crate::{
+    output::Solution, progress::RunSummary, reference, sink::SolutionSink, Candidate, Config,
+    CreateXVariant,
+};
+use alloy_primitives::FixedBytes;
+use rayon::prelude::*;
+use std::{
+    sync::atomic::Ordering,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Number of candidates tried between checks for being stopped.
+const BATCH: u64 = 1 << 12;
+
+/// Name of the device solutions found on the CPU are attributed to.
+pub const DEVICE: &str = "cpu";
+
+/// Candidate salts tried in batches on all cores.
+pub(crate) struct CpuMiner {
+    message: FixedBytes<4>,
+    nonce: u64,
+}
+
+impl CpuMiner {
+    pub fn new() -> Self {
+        Self {
+            message: FixedBytes::<4>::random(),
+            nonce: 0,
+        }
+    }
+
+    /// Candidates tried per batch.
+    pub fn candidates(&self) -> u64 {
+        BATCH
+    }
+
+    /// Tries the next batch of candidates, returning those meeting the
+    /// criterion.
+    pub fn batch(&mut self, config: &Config) -> Vec<Candidate> {
+        let hashes = match &config.create_variant {
+            CreateXVariant::Create2 { init_code_hashes } => init_code_hashes.len(),
+            CreateXVariant::Create3 => 1,
+        };
+        let message = self.message;
+        let found = (self.nonce..self.nonce + BATCH)
+            .into_par_iter()
+            .flat_map_iter(|nonce| {
+                let salt = config.salt(message.0, nonce);
+                (0..hashes).filter_map(move |index| {
+                    let address = reference::address(config, salt, index);
+                    reference::meets_criterion(&config.reward, &address).then_some(Candidate {
+                        salt,
+                        address,
+                        index,
+                    })
+                })
+            })
+            .collect();
+
+        // the salt holds seven bytes of the nonce
+        self.nonce += BATCH;
+        if self.nonce >= 1 << 56 {
+            self.message = FixedBytes::<4>::random();
+            self.nonce = 0;
+        }
+
+        found
+    }
+}
+
+/// Mines with the config on all cores, handing every solution to each of the
+/// sinks, until stopped. The loop count, kernel and progress settings of the
+/// config are ignored.
+pub fn cpu_with_sinks(
+    config: Config,
+    mut sinks: Vec<Box<dyn SolutionSink>>,
+) -> ocl::Result<RunSummary> {
+    let started = Instant::now();
+    let mut summary = RunSummary::default();
+    let mut miner = CpuMiner::new();
+
+    let result = loop {
+        summary.runtime = started.elapsed();
+        if let Some(run_summary) = &config.run_summary {
+            *run_summary.lock().unwrap() = summary.clone();
+        }
+        if config
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::SeqCst))
+        {
+            break Ok(summary);
+        }
+
+        let found = miner.batch(&config);
+        summary.hashes += miner.candidates();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let written = found.into_iter().try_for_each(|candidate| {
+            let solution = Solution::new(
+                &config,
+                candidate.salt,
+                candidate.address,
+                candidate.index,
+                DEVICE,
+                timestamp,
+            );
+            for sink in sinks.iter_mut() {
+                sink.write(&solution)
+                    .map_err(|e| format!("Could not write solution: {}", e))?;
+            }
+            summary.add(&solution);
+            Ok::<(), String>(())
+        });
+        if let Err(e) = written {
+            break Err(e.into());
+        }
+    };
+
+    for sink in sinks.iter_mut() {
+        sink.finish()
+            .map_err(|e| format!("Could not finish writing solutions: {}", e))?;
+    }
+
+    result
+}
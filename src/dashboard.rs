@@ -0,0 +1,421 @@
+//! Full-screen dashboard shown while mining on a terminal.
+
+use crate::{
+    estimate::{human, Estimate},
+    output::Solution,
+    progress::{hms, Controls, Status},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Sparkline, Table, TableState},
+    Frame, Terminal,
+};
+use separator::Separatable;
+use std::{
+    collections::VecDeque,
+    io::{self, Stdout},
+    time::{Duration, Instant},
+};
+
+/// Window over which the current rate of a device is averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Number of rate samples kept for the throughput history.
+const HISTORY: usize = 512;
+
+/// Number of messages kept for the log line.
+const LOG: usize = 2;
+
+/// Order in which the solutions are listed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum SortOrder {
+    #[default]
+    Newest,
+    Leading,
+    Total,
+}
+
+impl SortOrder {
+    fn next(self) -> Self {
+        match self {
+            SortOrder::Newest => SortOrder::Leading,
+            SortOrder::Leading => SortOrder::Total,
+            SortOrder::Total => SortOrder::Newest,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortOrder::Newest => "newest first",
+            SortOrder::Leading => "leading zero bytes",
+            SortOrder::Total => "total zero bytes",
+        }
+    }
+}
+
+/// Throughput measured for one device.
+struct Device {
+    name: String,
+    /// Times at which the number of candidates tried so far, in millions, was
+    /// sampled, over the rate window.
+    samples: VecDeque<(Instant, f64)>,
+    /// Millions of candidates per second averaged over the whole run.
+    average: f64,
+    work_size: u64,
+    cycles: u64,
+}
+
+impl Device {
+    /// Millions of candidates per second averaged over the rate window.
+    fn rate(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((start, first)), Some((end, last))) if end > start => {
+                (last - first) / (*end - *start).as_secs_f64()
+            }
+            _ => self.average,
+        }
+    }
+}
+
+/// Everything the dashboard shows.
+#[derive(Default)]
+struct View {
+    controls: Controls,
+    status: Option<Status>,
+    devices: Vec<Device>,
+    /// Total rate in thousands of candidates per second, sampled with every
+    /// status.
+    history: VecDeque<u64>,
+    /// The solutions in the order they were found, with the line shown for each.
+    solutions: Vec<(Solution, String)>,
+    sort: SortOrder,
+    table: TableState,
+    log: VecDeque<String>,
+}
+
+pub(crate) struct Dashboard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    view: View,
+}
+
+impl Dashboard {
+    /// Takes over the terminal until the dashboard is dropped.
+    pub fn open() -> io::Result<Self> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        terminal.clear()?;
+
+        Ok(Self {
+            terminal,
+            view: View::default(),
+        })
+    }
+
+    /// Adds a message to the log line.
+    pub fn log(&mut self, message: &str) {
+        if self.view.log.len() == LOG {
+            self.view.log.pop_front();
+        }
+        self.view.log.push_back(message.to_string());
+        // messages may come before the first status
+        let _ = self.draw();
+    }
+
+    /// Updates the statistics of the status' device and redraws.
+    pub fn status(&mut self, status: Status) -> io::Result<()> {
+        let now = Instant::now();
+        let view = &mut self.view;
+        let index = match view.devices.iter().position(|d| d.name == status.device) {
+            Some(index) => index,
+            None => {
+                view.devices.push(Device {
+                    name: status.device.clone(),
+                    samples: VecDeque::new(),
+                    average: 0.0,
+                    work_size: 0,
+                    cycles: 0,
+                });
+                view.devices.len() - 1
+            }
+        };
+        let device = &mut view.devices[index];
+        device.samples.push_back((now, status.attempts));
+        while device
+            .samples
+            .front()
+            .is_some_and(|(time, _)| now - *time > RATE_WINDOW)
+        {
+            device.samples.pop_front();
+        }
+        device.average = status.rate;
+        device.work_size = status.work_size;
+        device.cycles = status.cycles;
+
+        let rate: f64 = view.devices.iter().map(Device::rate).sum();
+        if view.history.len() == HISTORY {
+            view.history.pop_front();
+        }
+        view.history.push_back((rate * 1000.0) as u64);
+
+        view.status = Some(status);
+        self.draw()
+    }
+
+    /// Adds a solution to the list.
+    pub fn solution(&mut self, solution: Solution, line: String) {
+        self.view.solutions.push((solution, line));
+    }
+
+    /// Handles the pending key presses.
+    pub fn poll(&mut self) -> io::Result<Controls> {
+        let mut changed = false;
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    // raw mode turns Ctrl-C into a key press
+                    let code = match key.code {
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            KeyCode::Esc
+                        }
+                        code => code,
+                    };
+                    changed |= self.view.key(code);
+                }
+                Event::Resize(..) => changed = true,
+                _ => {}
+            }
+        }
+        if changed {
+            self.draw()?;
+        }
+
+        Ok(self.view.controls)
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        self.terminal.draw(|frame| self.view.render(frame))?;
+        Ok(())
+    }
+}
+
+impl Drop for Dashboard {
+    /// Gives the terminal back, and prints the solutions found, which would
+    /// otherwise disappear along with the dashboard.
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+
+        for (_, line) in &self.view.solutions {
+            println!("{}", line);
+        }
+    }
+}
+
+impl View {
+    /// Handles a key press, returning whether anything changed.
+    fn key(&mut self, code: KeyCode) -> bool {
+        let controls = &mut self.controls;
+        match code {
+            KeyCode::Char('p') | KeyCode::Char(' ') => controls.paused = !controls.paused,
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                controls.raise = controls.raise.saturating_add(1).min(20)
+            }
+            KeyCode::Char('-') => controls.raise = controls.raise.saturating_sub(1),
+            KeyCode::Char('q') | KeyCode::Esc => controls.quit = true,
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.scroll_up_by(1),
+            KeyCode::Down | KeyCode::Char('j') => self.table.scroll_down_by(1),
+            KeyCode::PageUp => self.table.scroll_up_by(10),
+            KeyCode::PageDown => self.table.scroll_down_by(10),
+            KeyCode::Home => self.table.select_first(),
+            KeyCode::End => self.table.select_last(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The solutions in the current sort order.
+    fn sorted(&self) -> Vec<&(Solution, String)> {
+        let mut solutions: Vec<_> = self.solutions.iter().collect();
+        match self.sort {
+            SortOrder::Newest => solutions.reverse(),
+            SortOrder::Leading => {
+                solutions.sort_by_key(|(s, _)| std::cmp::Reverse((s.leading_zeros, s.total_zeros)))
+            }
+            SortOrder::Total => {
+                solutions.sort_by_key(|(s, _)| std::cmp::Reverse((s.total_zeros, s.leading_zeros)))
+            }
+        }
+        solutions
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let [header, devices, middle, list, footer] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Length(self.devices.len().max(1) as u16 + 3),
+            Constraint::Length(7),
+            Constraint::Min(5),
+            Constraint::Length(LOG as u16 + 1),
+        ])
+        .areas(frame.area());
+        let [throughput, stats] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(middle);
+
+        // the search criteria, runtime and search space
+        let mut title = Line::from(" createXcrunch ".bold());
+        if self.controls.paused {
+            title.push_span(" paused ".black().on_yellow());
+        }
+        let lines = match &self.status {
+            Some(status) => vec![
+                Line::from(format!(
+                    "mining for {} address {}",
+                    status.variant, status.threshold
+                )),
+                Line::from(format!(
+                    "runtime {}    current search space {}",
+                    hms(status.runtime),
+                    status.search_space
+                )),
+            ],
+            None => vec![Line::from("setting up...")],
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            header,
+        );
+
+        // the throughput of each device
+        let rows = self.devices.iter().map(|device| {
+            Row::new([
+                device.name.clone(),
+                format!("{:.2}", device.rate()),
+                format!("{:.2}", device.average),
+                device.work_size.separated_string(),
+                device.cycles.to_string(),
+            ])
+        });
+        let widths = [
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ];
+        frame.render_widget(
+            Table::new(rows, widths)
+                .header(
+                    Row::new([
+                        "device",
+                        "rate (M/s, last 30s)",
+                        "rate (M/s, run)",
+                        "work size per cycle",
+                        "cycles",
+                    ])
+                    .bold(),
+                )
+                .block(Block::bordered().title(" devices ")),
+            devices,
+        );
+
+        // the recent throughput, newest on the right
+        let width = throughput.width.saturating_sub(2) as usize;
+        let skip = self.history.len().saturating_sub(width);
+        frame.render_widget(
+            Sparkline::default()
+                .data(self.history.iter().skip(skip))
+                .style(Style::default().fg(Color::Green))
+                .block(Block::bordered().title(" throughput ")),
+            throughput,
+        );
+
+        // found solutions, the best one and the expected time to the next
+        let rate: f64 = self.devices.iter().map(Device::rate).sum();
+        let eta = match &self.status {
+            Some(status) if rate > 0.0 => {
+                let estimate = Estimate::new(status.probability, rate);
+                format!(
+                    "~{} (90% within {})",
+                    human(estimate.expected_time()),
+                    human(estimate.percentile(0.9))
+                )
+            }
+            _ => "unknown".to_string(),
+        };
+        let best = self
+            .solutions
+            .iter()
+            .map(|(solution, _)| solution)
+            .max_by_key(|solution| (solution.leading_zeros, solution.total_zeros))
+            .map(|solution| {
+                format!(
+                    "{} ({} / {})",
+                    solution.checksum_address, solution.leading_zeros, solution.total_zeros
+                )
+            })
+            .unwrap_or_else(|| "none yet".to_string());
+        let mut lines = vec![
+            Line::from(format!("found:     {}", self.solutions.len())),
+            Line::from(format!("best:      {}", best)),
+            Line::from(format!("next hit:  {}", eta)),
+        ];
+        if self.controls.raise > 0 {
+            lines.push(Line::from(format!(
+                "threshold: raised by {} byte(s)",
+                self.controls.raise
+            )));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" stats ")),
+            stats,
+        );
+
+        // the solutions, scrollable
+        let rows: Vec<Row> = self
+            .sorted()
+            .into_iter()
+            .map(|(solution, _)| {
+                Row::new([
+                    solution.checksum_address.clone(),
+                    format!("{} / {}", solution.leading_zeros, solution.total_zeros),
+                    solution.salt.clone(),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(42),
+            Constraint::Length(7),
+            Constraint::Min(66),
+        ];
+        frame.render_stateful_widget(
+            Table::new(rows, widths)
+                .header(Row::new(["address", "zeros", "salt"]).bold())
+                .row_highlight_style(Style::default().reversed())
+                .block(
+                    Block::bordered()
+                        .title(format!(" solutions, by {} ", self.sort.name()))
+                        .title_bottom(" s sort  ↑↓ scroll "),
+                ),
+            list,
+            &mut self.table,
+        );
+
+        // the key bindings and recent messages
+        let mut lines = vec![Line::from(
+            "p pause/resume   + raise threshold   - lower threshold   q quit".dim(),
+        )];
+        lines.extend(self.log.iter().map(|message| Line::from(message.as_str())));
+        frame.render_widget(Paragraph::new(lines), footer);
+    }
+}
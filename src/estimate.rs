@@ -0,0 +1,79 @@
+//! Estimating how long a search takes.
+//!
+//! Every candidate salt independently yields an address meeting the criterion
+//! with the same probability, so the time to the next hit is exponentially
+//! distributed, whatever has been tried before.
+
+use std::time::Duration;
+
+/// Expected time above which a job is considered infeasible.
+pub const INFEASIBLE: Duration = Duration::from_secs(365 * 24 * 3600);
+
+/// The probability of a candidate salt meeting the criterion for at least one
+/// of `hashes` init code hashes, given the probability for one.
+pub fn per_salt(probability: f64, hashes: usize) -> f64 {
+    // 1 - (1 - p)^n, without losing tiny probabilities to rounding
+    -(hashes as f64 * (-probability).ln_1p()).exp_m1()
+}
+
+/// The time to the next hit of a search.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    /// Probability of a candidate salt meeting the criterion.
+    pub probability: f64,
+    /// Millions of candidates tried per second.
+    pub rate: f64,
+}
+
+impl Estimate {
+    pub fn new(probability: f64, rate: f64) -> Self {
+        Self { probability, rate }
+    }
+
+    /// The expected number of candidates tried per hit.
+    pub fn expected_candidates(&self) -> f64 {
+        1.0 / self.probability
+    }
+
+    /// The expected time to the next hit, if it is finite.
+    pub fn expected_time(&self) -> Option<Duration> {
+        Duration::try_from_secs_f64(1.0 / self.hits_per_second()).ok()
+    }
+
+    /// The time within which the next hit comes with probability `q`, if it is
+    /// finite.
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        Duration::try_from_secs_f64(-(-q).ln_1p() / self.hits_per_second()).ok()
+    }
+
+    fn hits_per_second(&self) -> f64 {
+        self.probability * self.rate * 1e6
+    }
+
+    /// Whether the next hit is expected within [`INFEASIBLE`].
+    pub fn feasible(&self) -> bool {
+        self.expected_time().is_some_and(|time| time <= INFEASIBLE)
+    }
+}
+
+/// Formats a possibly very long duration for people.
+pub fn human(duration: Option<Duration>) -> String {
+    const DAY: u64 = 24 * 3600;
+    const YEAR: u64 = 365 * DAY;
+
+    let Some(duration) = duration else {
+        return "forever".to_string();
+    };
+    let secs = duration.as_secs();
+    match secs {
+        _ if secs >= 1000 * YEAR => format!("{:.2e} years", secs as f64 / YEAR as f64),
+        _ if secs >= YEAR => format!("{:.1} years", secs as f64 / YEAR as f64),
+        _ if secs >= DAY => format!(
+            "{}d {}",
+            secs / DAY,
+            crate::progress::hms(Duration::from_secs(secs % DAY))
+        ),
+        _ if duration < Duration::from_secs(1) => format!("{}ms", duration.as_millis()),
+        _ => crate::progress::hms(duration),
+    }
+}
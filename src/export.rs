@@ -0,0 +1,244 @@
+//! Exports found salts to the tools used to deploy with them.
+
+use crate::{
+    calldata::{amount, decode, read_init_code},
+    output::Solution,
+};
+use alloy_primitives::{hex, keccak256, U256};
+use std::fmt::Write as _;
+
+/// Where a generated script takes the init code from.
+pub enum InitCode {
+    /// A Foundry artifact such as `Token.sol:Token`, loaded with `vm.getCode`
+    /// and followed by the ABI-encoded constructor arguments.
+    Artifact {
+        artifact: String,
+        constructor_args: Vec<u8>,
+    },
+    /// The init code itself.
+    Bytes(Vec<u8>),
+}
+
+impl InitCode {
+    /// Builds the init code source from the command line arguments: either an
+    /// artifact with optional constructor arguments, or the init code in hex
+    /// or as `@<path>`.
+    pub fn from_args(
+        artifact: Option<&str>,
+        constructor_args: Option<&str>,
+        init_code: Option<&str>,
+    ) -> Result<Self, String> {
+        match (artifact, init_code) {
+            (Some(artifact), None) => Ok(Self::Artifact {
+                artifact: artifact.to_string(),
+                constructor_args: constructor_args
+                    .map(|args| decode(args, "constructor arguments"))
+                    .transpose()?
+                    .unwrap_or_default(),
+            }),
+            (None, Some(init_code)) => Ok(Self::Bytes(read_init_code(init_code)?)),
+            _ => Err("exactly one of the artifact or the init code is required".to_string()),
+        }
+    }
+}
+
+/// A call made to the contract right after deploying it, using one of the
+/// `...AndInit` functions of CreateX.
+pub struct InitCall {
+    pub data: Vec<u8>,
+    pub constructor_amount: U256,
+    pub init_call_amount: U256,
+}
+
+impl InitCall {
+    /// Builds the init call from its calldata in hex and the amounts in wei.
+    pub fn from_args(
+        data: &str,
+        constructor_amount: Option<&str>,
+        init_call_amount: Option<&str>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            data: decode(data, "init call")?,
+            constructor_amount: amount(constructor_amount, "constructor amount")?,
+            init_call_amount: amount(init_call_amount, "init call amount")?,
+        })
+    }
+}
+
+/// Picks the solution with the given salt, or the only one if no salt is
+/// given.
+pub fn select_solution(solutions: Vec<Solution>, salt: Option<&str>) -> Result<Solution, String> {
+    match salt {
+        Some(salt) => {
+            let salt = hex::encode_prefixed(decode(salt, "salt")?);
+            solutions
+                .into_iter()
+                .find(|solution| solution.salt == salt)
+                .ok_or_else(|| format!("no record has the salt {}", salt))
+        }
+        None => match <[Solution; 1]>::try_from(solutions) {
+            Ok([solution]) => Ok(solution),
+            Err(solutions) => Err(format!(
+                "the file holds {} records, pass --salt to pick one",
+                solutions.len()
+            )),
+        },
+    }
+}
+
+/// Generates a Foundry script deploying the contract with the salt of
+/// `solution`, through the same factory and with the same caller and chain as
+/// it was mined for. The script checks the address CreateX predicts before
+/// broadcasting, and the address it deployed to afterwards.
+pub fn foundry_script(
+    solution: &Solution,
+    contract_name: &str,
+    init_code: &InitCode,
+    init_call: Option<&InitCall>,
+) -> Result<String, String> {
+    let create3 = match solution.create_variant.as_str() {
+        "create2" => false,
+        "create3" => true,
+        variant => return Err(format!("unknown deployment variant {}", variant)),
+    };
+    let init_code_hash = match (&solution.init_code_hash, create3) {
+        (Some(init_code_hash), false) => Some(init_code_hash),
+        (None, false) => return Err("CREATE2 records must have an init code hash".to_string()),
+        (_, true) => None,
+    };
+    if let (Some(init_code_hash), InitCode::Bytes(init_code)) = (init_code_hash, init_code) {
+        if hex::encode_prefixed(keccak256(init_code)) != *init_code_hash {
+            return Err(format!(
+                "the init code does not match the init code hash {} the salt was mined for",
+                init_code_hash
+            ));
+        }
+    }
+
+    let mut constants = String::new();
+    let mut chain_check = String::new();
+    let mut checks = String::new();
+    if let Some(caller) = &solution.caller {
+        writeln!(
+            constants,
+            "    address internal constant CALLER = {caller};"
+        )
+        .unwrap();
+    }
+    if let Some(chain_id) = solution.chain_id {
+        writeln!(
+            constants,
+            "    uint256 internal constant CHAIN_ID = {chain_id};"
+        )
+        .unwrap();
+        writeln!(
+            chain_check,
+            "        require(block.chainid == CHAIN_ID, \"the salt is protected for another chain\");"
+        )
+        .unwrap();
+    }
+    if let Some(init_code_hash) = init_code_hash {
+        writeln!(
+            constants,
+            "    bytes32 internal constant INIT_CODE_HASH = {init_code_hash};"
+        )
+        .unwrap();
+    }
+
+    let init_code = match init_code {
+        InitCode::Artifact {
+            artifact,
+            constructor_args,
+        } => format!(
+            "abi.encodePacked(vm.getCode(\"{}\"), hex\"{}\")",
+            artifact,
+            hex::encode(constructor_args)
+        ),
+        InitCode::Bytes(init_code) => format!("hex\"{}\"", hex::encode(init_code)),
+    };
+    let predicted = match create3 {
+        true => "CREATEX.computeCreate3Address(GUARDED_SALT)".to_string(),
+        false => {
+            writeln!(
+                checks,
+                "        require(keccak256(initCode) == INIT_CODE_HASH, \"the init code does not match the mined init code hash\");"
+            )
+            .unwrap();
+            "CREATEX.computeCreate2Address(GUARDED_SALT, INIT_CODE_HASH)".to_string()
+        }
+    };
+    let broadcast = match solution.caller {
+        Some(_) => "vm.startBroadcast(CALLER);",
+        None => "vm.startBroadcast();",
+    };
+    let create = if create3 { "Create3" } else { "Create2" };
+    let deploy = match init_call {
+        Some(InitCall {
+            data,
+            constructor_amount,
+            init_call_amount,
+        }) => format!(
+            "CREATEX.deploy{}AndInit{{value: {}}}(SALT, initCode, hex\"{}\", ICreateX.Values({}, {}))",
+            create,
+            constructor_amount + init_call_amount,
+            hex::encode(data),
+            constructor_amount,
+            init_call_amount
+        ),
+        None => format!("CREATEX.deploy{create}(SALT, initCode)"),
+    };
+
+    Ok(format!(
+        r#"// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.23;
+
+import {{Script}} from "forge-std/Script.sol";
+
+/// @notice Deploys {address} through CreateX, with a salt found by createXcrunch {criterion}.
+contract {contract_name} is Script {{
+    ICreateX internal constant CREATEX = ICreateX({factory});
+    bytes32 internal constant SALT = {salt};
+    // the salt as CreateX guards it for the caller and chain
+    bytes32 internal constant GUARDED_SALT = {guarded_salt};
+    address internal constant EXPECTED = {address};
+{constants}
+    function run() external returns (address deployed) {{
+{chain_check}        require(address(CREATEX).code.length != 0, "CreateX is not deployed on this chain");
+        bytes memory initCode = {init_code};
+{checks}        require({predicted} == EXPECTED, "CreateX predicts another address");
+
+        {broadcast}
+        deployed = {deploy};
+        vm.stopBroadcast();
+
+        require(deployed == EXPECTED, "deployed to an unexpected address");
+    }}
+}}
+
+interface ICreateX {{
+    struct Values {{
+        uint256 constructorAmount;
+        uint256 initCallAmount;
+    }}
+
+    function deployCreate2(bytes32 salt, bytes memory initCode) external payable returns (address newContract);
+    function deployCreate2AndInit(bytes32 salt, bytes memory initCode, bytes memory data, Values memory values)
+        external
+        payable
+        returns (address newContract);
+    function deployCreate3(bytes32 salt, bytes memory initCode) external payable returns (address newContract);
+    function deployCreate3AndInit(bytes32 salt, bytes memory initCode, bytes memory data, Values memory values)
+        external
+        payable
+        returns (address newContract);
+    function computeCreate2Address(bytes32 salt, bytes32 initCodeHash) external view returns (address computedAddress);
+    function computeCreate3Address(bytes32 salt) external view returns (address computedAddress);
+}}
+"#,
+        address = solution.checksum_address,
+        criterion = solution.criterion,
+        factory = solution.factory,
+        salt = solution.salt,
+        guarded_salt = solution.guarded_salt,
+    ))
+}
@@ -0,0 +1,530 @@
+//! Mining jobs, as described in job files and submitted to the job service.
+//!
+//! A job file holds a list of jobs in TOML or JSON, each with the settings of
+//! the `create2` and `create3` commands and a stop condition:
+//!
+//! ```toml
+//! schedule = "round-robin"
+//! slice = 300
+//!
+//! [[jobs]]
+//! name = "token"
+//! variant = "create2"
+//! init_code_hashes = ["0x…"]
+//! caller = "0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"
+//! crosschain = 1
+//! leading = 4
+//! max_solutions = 1
+//! output = "token.txt"
+//! ```
+
+use crate::{
+    calldata::Deployment,
+    cpu, gpu_with_sinks,
+    output::{OutputFormat, Solution},
+    progress::{Progress, RunSummary},
+    scheduler::{self, Job, JobSource},
+    sink::{FileSink, SolutionSink, StdoutSink},
+    Config, RewardVariant, MAX_LOOP_COUNT,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Seconds each job mines for on its turn when round-robin, unless set in the
+/// job file.
+pub const DEFAULT_SLICE: u64 = 60;
+
+/// Where jobs are mined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The GPU with the given device ID.
+    Gpu(u8),
+    /// All cores of the CPU, see [`cpu`].
+    Cpu,
+}
+
+impl Backend {
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Backend::Gpu(device) => format!("gpu {}", device),
+            Backend::Cpu => cpu::DEVICE.to_string(),
+        }
+    }
+
+    pub(crate) fn gpu_device(&self) -> u8 {
+        match self {
+            Backend::Gpu(device) => *device,
+            Backend::Cpu => 0,
+        }
+    }
+
+    /// Mines with the config, handing every solution to each of the sinks,
+    /// until stopped.
+    pub fn mine(
+        &self,
+        config: Config,
+        sinks: Vec<Box<dyn SolutionSink>>,
+    ) -> ocl::Result<RunSummary> {
+        match self {
+            Backend::Gpu(_) => gpu_with_sinks(config, sinks),
+            Backend::Cpu => cpu::cpu_with_sinks(config, sinks),
+        }
+    }
+}
+
+/// Kind of address a job mines for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobVariant {
+    Create2,
+    Create3,
+}
+
+/// A job, with the same settings as the `create2` and `create3` commands.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobSpec {
+    /// Name the job is reported under.
+    pub name: Option<String>,
+    /// Defaults to CREATE2 with init code hashes, and to CREATE3 without.
+    pub variant: Option<JobVariant>,
+    /// Defaults to the CreateX factory.
+    pub factory: Option<String>,
+    pub caller: Option<String>,
+    #[serde(alias = "crosschain")]
+    pub chain_id: Option<u64>,
+    pub init_code_hashes: Vec<String>,
+    /// Init code of the contracts in hex, to add the calldata deploying them to
+    /// the solutions.
+    pub init_code: Vec<String>,
+    pub leading: Option<u8>,
+    pub total: Option<u8>,
+    pub either: bool,
+    pub pattern: Option<String>,
+    pub loop_count: Option<u32>,
+    pub generic_kernel: bool,
+    /// Skip the self-test of the kernel.
+    pub skip_self_test: bool,
+    /// Number of solutions after which the job is done. The last kernel
+    /// dispatch may find a few more.
+    pub max_solutions: Option<usize>,
+    /// Seconds of mining after which the job is done.
+    pub max_time: Option<u64>,
+    /// File the solutions are appended to. Defaults to `output.txt`.
+    pub output: Option<String>,
+    pub format: OutputFormat,
+    /// Share of the device relative to the jobs mined alongside it. Defaults
+    /// to 1.
+    pub weight: Option<u32>,
+}
+
+impl JobSpec {
+    /// The config mining the job on the GPU with the given device ID, writing
+    /// to `output`.
+    pub fn config<'a>(&self, gpu_device: u8, output: &'a str) -> Result<Config<'a>, String> {
+        if self.weight == Some(0) {
+            return Err("the weight must be at least 1".to_string());
+        }
+        match (self.variant, self.init_code_hashes.is_empty()) {
+            (Some(JobVariant::Create2), true) => {
+                return Err("CREATE2 jobs need init code hashes".to_string())
+            }
+            (Some(JobVariant::Create3), false) => {
+                return Err("CREATE3 jobs take no init code hashes".to_string())
+            }
+            _ => (),
+        }
+
+        let reward = RewardVariant::from_args(
+            self.leading,
+            self.total,
+            self.either,
+            self.pattern.as_deref(),
+        )?;
+        let init_code_hashes = self
+            .init_code_hashes
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut config = Config::new(
+            gpu_device,
+            self.factory
+                .as_deref()
+                .unwrap_or("0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed"),
+            self.caller.as_deref(),
+            self.chain_id,
+            (!init_code_hashes.is_empty()).then_some(init_code_hashes.as_slice()),
+            reward,
+            output,
+        )?;
+
+        if let Some(loop_count) = self.loop_count {
+            if !(1..=MAX_LOOP_COUNT).contains(&loop_count) {
+                return Err(format!("loop_count must be between 1 and {MAX_LOOP_COUNT}"));
+            }
+            config.loop_count = loop_count;
+        }
+        config.generic_kernel = self.generic_kernel;
+        config.self_test = !self.skip_self_test;
+        config.format = self.format;
+        config.progress = Progress::None;
+        if !self.init_code.is_empty() {
+            let deployments = self
+                .init_code
+                .iter()
+                .map(|code| Deployment::from_args(Some(code), None, None, None, None))
+                .collect::<Result<Vec<_>, _>>()?;
+            config.set_deployments(deployments)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Whether the job has met its stop condition.
+    pub fn done(&self, summary: &RunSummary) -> bool {
+        self.max_solutions
+            .is_some_and(|max| summary.found() >= max as u64)
+            || self
+                .max_time
+                .is_some_and(|max| summary.runtime >= Duration::from_secs(max))
+    }
+
+    /// The file the solutions are appended to.
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or("output.txt")
+    }
+
+    fn has_stop_condition(&self) -> bool {
+        self.max_solutions.is_some() || self.max_time.is_some()
+    }
+}
+
+/// How the jobs of a job file take turns on the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Schedule {
+    /// Each job runs until it meets its stop condition, then the next starts.
+    #[default]
+    InOrder,
+    /// The jobs take turns of `slice` seconds, until each meets its stop
+    /// condition.
+    RoundRobin,
+    /// The jobs are mined at once, taking turns by kernel dispatch in
+    /// proportion to their weights, until each meets its stop condition.
+    Fair,
+}
+
+/// A list of jobs to mine one after another.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobFile {
+    pub schedule: Schedule,
+    /// Seconds each job mines for on its turn, when round-robin. Defaults to
+    /// [`DEFAULT_SLICE`].
+    pub slice: Option<u64>,
+    pub jobs: Vec<JobSpec>,
+}
+
+impl JobFile {
+    /// Reads a job file, in JSON if its name ends in `.json` and in TOML
+    /// otherwise.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let file = match path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            true => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            false => toml::from_str(&contents).map_err(|e| e.to_string()),
+        };
+        file.map_err(|e| format!("invalid job file {}: {}", path.display(), e))
+    }
+
+    /// The name of the job at `index`.
+    pub fn label(&self, index: usize) -> String {
+        match &self.jobs[index].name {
+            Some(name) => name.clone(),
+            None => format!("job {}", index + 1),
+        }
+    }
+
+    /// Checks every job before any of them runs.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.jobs.is_empty() {
+            return Err("the job file has no jobs".to_string());
+        }
+        if self.slice == Some(0) {
+            return Err("the slice must be at least a second".to_string());
+        }
+        for (index, spec) in self.jobs.iter().enumerate() {
+            spec.config(0, spec.output())
+                .map_err(|e| format!("{}: {}", self.label(index), e))?;
+            // in order, the jobs after one that never ends would never run
+            if self.schedule == Schedule::InOrder
+                && index + 1 < self.jobs.len()
+                && !spec.has_stop_condition()
+            {
+                return Err(format!(
+                    "{}: needs max_solutions or max_time to let the jobs after it run",
+                    self.label(index)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the jobs of the file on the backend until each has met its stop
+/// condition, or `interrupt` is set. Returns what each job has done.
+pub fn run(
+    file: &JobFile,
+    backend: Backend,
+    progress: Progress,
+    interrupt: &Arc<AtomicBool>,
+) -> Result<Vec<RunSummary>, String> {
+    file.validate()?;
+    let announce = !matches!(progress, Progress::Json | Progress::None);
+    let slice = match file.schedule {
+        Schedule::InOrder => None,
+        Schedule::RoundRobin => Some(Duration::from_secs(file.slice.unwrap_or(DEFAULT_SLICE))),
+        Schedule::Fair => return run_fair(file, backend, progress, interrupt),
+    };
+
+    let mut summaries = vec![RunSummary::default(); file.jobs.len()];
+    // the kernel of a job is only checked on its first turn
+    let mut tested = vec![false; file.jobs.len()];
+    let mut active = (0..file.jobs.len()).collect::<Vec<_>>();
+    while !active.is_empty() && !interrupt.load(Ordering::SeqCst) {
+        for index in active.clone() {
+            if interrupt.load(Ordering::SeqCst) {
+                break;
+            }
+            let spec = &file.jobs[index];
+            let summary = &mut summaries[index];
+            if announce {
+                println!("Running {}...", file.label(index));
+            }
+
+            let limit = spec
+                .max_time
+                .map(|max| Duration::from_secs(max).saturating_sub(summary.runtime))
+                .into_iter()
+                .chain(slice)
+                .min();
+            let solutions = spec
+                .max_solutions
+                .map(|max| max.saturating_sub(summary.found() as usize));
+            let self_test = !std::mem::replace(&mut tested[index], true);
+            let ran = run_turn(
+                spec, backend, progress, limit, solutions, self_test, interrupt,
+            )
+            .map_err(|e| format!("{}: {}", file.label(index), e))?;
+            summary.merge(&ran);
+
+            if spec.done(summary) {
+                active.retain(|active| *active != index);
+            }
+        }
+    }
+
+    Ok(summaries)
+}
+
+/// Mines the job until it has run for `limit`, found `solutions` more
+/// solutions or `interrupt` is set. The kernel is checked first if the job
+/// asks for it and `self_test` is set.
+fn run_turn(
+    spec: &JobSpec,
+    backend: Backend,
+    progress: Progress,
+    limit: Option<Duration>,
+    solutions: Option<usize>,
+    self_test: bool,
+    interrupt: &Arc<AtomicBool>,
+) -> Result<RunSummary, String> {
+    let (mut config, sinks, stop) = prepare(spec, backend, solutions)?;
+    config.progress = progress;
+    config.self_test &= self_test;
+
+    let _watch = stop_when(stop, limit, Some(interrupt.clone()));
+    backend.mine(config, sinks).map_err(|e| e.to_string())
+}
+
+/// The config and sinks mining the job on the backend, along with the flag
+/// stopping it. The flag is set once it has found `solutions` more solutions.
+#[allow(clippy::type_complexity)]
+fn prepare(
+    spec: &JobSpec,
+    backend: Backend,
+    solutions: Option<usize>,
+) -> Result<(Config<'_>, Vec<Box<dyn SolutionSink>>, Arc<AtomicBool>), String> {
+    let mut config = spec.config(backend.gpu_device(), spec.output())?;
+    let stop = Arc::new(AtomicBool::new(false));
+    config.stop = Some(stop.clone());
+
+    let file = FileSink::for_config(&config)
+        .map_err(|e| format!("could not create or open {}: {}", config.output, e))?;
+    let mut sinks: Vec<Box<dyn SolutionSink>> = vec![Box::new(file)];
+    if let Some(remaining) = solutions {
+        sinks.push(Box::new(Limit {
+            remaining,
+            stop: stop.clone(),
+        }));
+    }
+
+    Ok((config, sinks, stop))
+}
+
+/// Mines all jobs of the file at once, sharing the device between them.
+fn run_fair(
+    file: &JobFile,
+    backend: Backend,
+    progress: Progress,
+    interrupt: &Arc<AtomicBool>,
+) -> Result<Vec<RunSummary>, String> {
+    let announce = !matches!(progress, Progress::Json | Progress::None);
+    if announce {
+        println!(
+            "Sharing {} between {} jobs...",
+            backend.name(),
+            file.jobs.len()
+        );
+    }
+    let mut source = FileJobs {
+        file,
+        backend,
+        announce,
+        interrupt,
+        next: 0,
+        watches: (0..file.jobs.len()).map(|_| None).collect(),
+        results: vec![None; file.jobs.len()],
+    };
+    scheduler::share(backend, progress, &mut source).map_err(|e| e.to_string())?;
+
+    // jobs never started when interrupted have done nothing
+    source
+        .results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            result
+                .unwrap_or_else(|| Ok(RunSummary::default()))
+                .map_err(|e| format!("{}: {}", file.label(index), e))
+        })
+        .collect()
+}
+
+/// Hands the jobs of a file to the scheduler all at once.
+struct FileJobs<'a> {
+    file: &'a JobFile,
+    backend: Backend,
+    announce: bool,
+    interrupt: &'a Arc<AtomicBool>,
+    /// Index of the next job to admit.
+    next: usize,
+    /// Guards of the time limits of the running jobs.
+    watches: Vec<Option<mpsc::Sender<()>>>,
+    results: Vec<Option<Result<RunSummary, String>>>,
+}
+
+impl<'a> JobSource<'a> for FileJobs<'a> {
+    fn next(&mut self, _: usize) -> Option<Job<'a>> {
+        while self.next < self.file.jobs.len() && !self.interrupt.load(Ordering::SeqCst) {
+            let index = self.next;
+            self.next += 1;
+            let spec = &self.file.jobs[index];
+
+            match prepare(spec, self.backend, spec.max_solutions) {
+                Ok((config, mut sinks, stop)) => {
+                    if self.announce {
+                        sinks.push(Box::new(StdoutSink::new(
+                            OutputFormat::Text,
+                            config.multiple_init_code_hashes(),
+                        )));
+                    }
+                    self.watches[index] = Some(stop_when(
+                        stop,
+                        spec.max_time.map(Duration::from_secs),
+                        Some(self.interrupt.clone()),
+                    ));
+                    return Some(Job {
+                        id: index as u64,
+                        config,
+                        sinks,
+                        weight: spec.weight.unwrap_or(1),
+                    });
+                }
+                Err(e) => self.results[index] = Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    fn finished(&mut self, id: u64, result: Result<RunSummary, String>) {
+        self.watches[id as usize] = None;
+        self.results[id as usize] = Some(result);
+    }
+}
+
+/// Stops a run once it has found enough solutions.
+struct Limit {
+    remaining: usize,
+    stop: Arc<AtomicBool>,
+}
+
+impl SolutionSink for Limit {
+    fn write(&mut self, _: &Solution) -> io::Result<()> {
+        self.remaining = self.remaining.saturating_sub(1);
+        if self.remaining == 0 {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/// Sets `stop` once `limit` has passed or `interrupt` is set, until the
+/// returned guard is dropped.
+pub(crate) fn stop_when(
+    stop: Arc<AtomicBool>,
+    limit: Option<Duration>,
+    interrupt: Option<Arc<AtomicBool>>,
+) -> mpsc::Sender<()> {
+    let (guard, dropped) = mpsc::channel::<()>();
+    let deadline = limit.map(|limit| Instant::now() + limit);
+    thread::spawn(move || loop {
+        let wait = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => Duration::MAX,
+        };
+        // wake up now and then to check for an interrupt
+        let wait = match interrupt {
+            Some(_) => wait.min(Duration::from_millis(100)),
+            None => wait,
+        };
+        match dropped.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => return,
+        }
+        if interrupt
+            .as_ref()
+            .is_some_and(|interrupt| interrupt.load(Ordering::SeqCst))
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            stop.store(true, Ordering::SeqCst);
+            return;
+        }
+    });
+    guard
+}
@@ -0,0 +1,262 @@
+//! Host-side evaluation of keccak-f[1600] rounds for specialising the kernel.
+//!
+//! Most of every sponge the kernel absorbs is known before mining starts (the
+//! factory, the caller, the chain ID, the init code hash and the padding).
+//! Rather than having each work item rebuild those bytes, the host folds them
+//! into the first round of the permutation and emits OpenCL code that only
+//! mixes in the lanes that actually change between candidates.
+
+use std::fmt::Write as _;
+
+/// Rotation offsets for rho, indexed by `x + 5 * y`.
+const RHO: [u32; 25] = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+/// Round constant of the first round.
+const IOTA_0: u64 = 0x0000000000000001;
+
+/// A 64-bit lane of the sponge state, split into the part that is known on
+/// the host and the device expressions that are xor-ed into it.
+#[derive(Clone, Default)]
+pub(crate) struct Lane {
+    constant: u64,
+    terms: Vec<String>,
+}
+
+impl Lane {
+    pub(crate) fn constant(constant: u64) -> Self {
+        Self {
+            constant,
+            terms: vec![],
+        }
+    }
+
+    /// Mixes a device expression into the lane. The expression must not set
+    /// any bit that is already set in the constant part.
+    pub(crate) fn with(mut self, term: impl Into<String>) -> Self {
+        self.terms.push(term.into());
+        self
+    }
+
+    fn is_constant(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    fn xor(&self, other: &Lane) -> Lane {
+        Lane {
+            constant: self.constant ^ other.constant,
+            terms: self.terms.iter().chain(&other.terms).cloned().collect(),
+        }
+    }
+
+    fn expr(&self) -> String {
+        let mut parts = self.terms.clone();
+        if self.constant != 0 || parts.is_empty() {
+            parts.push(format!("0x{:016x}UL", self.constant));
+        }
+        parts.join(" ^ ")
+    }
+
+    /// Binds the lane to a temporary on the device unless it is a constant or
+    /// already a single term.
+    fn bind(&self, src: &mut String, name: String) -> Lane {
+        if self.constant == 0 && self.terms.len() == 1 {
+            return self.clone();
+        }
+        self.assign(src, name)
+    }
+
+    /// Binds the lane to a temporary on the device unless it is a constant.
+    fn assign(&self, src: &mut String, name: String) -> Lane {
+        if self.is_constant() {
+            return self.clone();
+        }
+        writeln!(src, "  ulong {name} = {}; \\", self.expr()).unwrap();
+        Lane::constant(0).with(name)
+    }
+}
+
+/// Splits a byte-level sponge template into its 25 little-endian lanes.
+pub(crate) fn lanes(sponge: &[u8; 200]) -> [Lane; 25] {
+    std::array::from_fn(|i| {
+        let mut lane = [0u8; 8];
+        lane.copy_from_slice(&sponge[8 * i..8 * i + 8]);
+        Lane::constant(u64::from_le_bytes(lane))
+    })
+}
+
+/// Mixes `expr`, a little-endian device value of `len` bytes, into the lanes
+/// it covers when absorbed at byte `offset` of the sponge. The bytes it covers
+/// must be zero in the template.
+pub(crate) fn absorb(lanes: &mut [Lane; 25], offset: usize, len: usize, expr: &str) {
+    let covered = lanes.iter_mut().enumerate();
+    for (i, lane) in covered.take((offset + len - 1) / 8 + 1).skip(offset / 8) {
+        let term = match 8 * (offset as isize - 8 * i as isize) {
+            0 => expr.to_string(),
+            shift if shift > 0 => format!("({expr} << {shift})"),
+            shift => format!("({expr} >> {})", -shift),
+        };
+        *lane = std::mem::take(lane).with(term);
+    }
+}
+
+/// Emits a macro `NAME(a)` that takes the sponge state described by `lanes`
+/// through the first round of keccak-f[1600] and stores the result in `a`.
+/// Work that only depends on constant lanes is done here on the host.
+pub(crate) fn first_round(name: &str, lanes: &[Lane; 25]) -> String {
+    let mut src = String::new();
+    writeln!(src, "#define {name}(a) {{ \\").unwrap();
+
+    // theta: column parities
+    let c: Vec<Lane> = (0..5)
+        .map(|x| {
+            let parity = (0..5).fold(Lane::constant(0), |acc, y| acc.xor(&lanes[x + 5 * y]));
+            parity.bind(&mut src, format!("c{x}"))
+        })
+        .collect();
+
+    let d: Vec<Lane> = (0..5)
+        .map(|x| {
+            let d = c[(x + 4) % 5].xor(&rotate(&mut src, &c[(x + 1) % 5], 1, format!("r{x}")));
+            d.assign(&mut src, format!("d{x}"))
+        })
+        .collect();
+
+    // theta, rho and pi: b[y + 5 * ((2x + 3y) % 5)] = ROL(a[x + 5 * y] ^ d[x], r)
+    let mut b = vec![Lane::default(); 25];
+    for y in 0..5 {
+        for (x, d) in d.iter().enumerate() {
+            let i = x + 5 * y;
+            let j = y + 5 * ((2 * x + 3 * y) % 5);
+            let lane = lanes[i].xor(d).bind(&mut src, format!("e{i}"));
+            b[j] = rotate(&mut src, &lane, RHO[i], format!("b{j}"));
+        }
+    }
+
+    // chi and iota
+    let mut out = String::new();
+    for y in 0..5 {
+        for x in 0..5 {
+            let i = x + 5 * y;
+            let mut lane = b[i].xor(&and_not(&b[(x + 1) % 5 + 5 * y], &b[(x + 2) % 5 + 5 * y]));
+            if i == 0 {
+                lane.constant ^= IOTA_0;
+            }
+            writeln!(out, "  a[{i}] = {}; \\", lane.expr()).unwrap();
+        }
+    }
+
+    src.push_str(&out);
+    src.push_str("}\n");
+    src
+}
+
+fn rotate(src: &mut String, lane: &Lane, n: u32, name: String) -> Lane {
+    if lane.is_constant() {
+        return Lane::constant(lane.constant.rotate_left(n));
+    }
+    let lane = lane.bind(src, name);
+    if n == 0 {
+        return lane;
+    }
+    Lane::constant(0).with(format!("ROL({}, {n}u)", lane.terms[0]))
+}
+
+/// `~x & y`, folding whatever is known on the host.
+fn and_not(x: &Lane, y: &Lane) -> Lane {
+    match (x.is_constant(), y.is_constant()) {
+        (true, true) => Lane::constant(!x.constant & y.constant),
+        (true, false) => match !x.constant {
+            0 => Lane::constant(0),
+            u64::MAX => y.clone(),
+            mask => Lane::constant(0).with(format!("(0x{mask:016x}UL & {})", y.terms[0])),
+        },
+        (false, true) => match y.constant {
+            0 => Lane::constant(0),
+            u64::MAX => Lane::constant(0).with(format!("~{}", x.terms[0])),
+            mask => Lane::constant(0).with(format!("(~{} & 0x{mask:016x}UL)", x.terms[0])),
+        },
+        (false, false) => Lane::constant(0).with(format!("(~{} & {})", x.terms[0], y.terms[0])),
+    }
+}
+
+/// Emits `FINAL_ROUND_EARLY(a)` and `FINAL_ROUND_REST(a)`, which together
+/// compute the last round of keccak-f[1600] for the five 32-bit words of the
+/// address only. The first macro computes the words in `early` (indices into
+/// the address), so that a candidate can be rejected before the second one
+/// computes the rest.
+pub fn final_round(early: &[usize]) -> String {
+    let mut src = String::new();
+    let mut computed = [false; 5];
+
+    writeln!(src, "#define FINAL_ROUND_EARLY(a) \\").unwrap();
+    // every address word depends on all five column parities, so they are
+    // always computed up front, before any of the state is overwritten
+    for x in 0..5 {
+        let column = (0..5).map(|y| format!("a[{}]", x + 5 * y));
+        writeln!(
+            src,
+            "  ulong fc{x} = {}; \\",
+            column.collect::<Vec<_>>().join(" ^ ")
+        )
+        .unwrap();
+    }
+    final_words(&mut src, early, &mut computed);
+    src.push('\n');
+
+    writeln!(src, "#define FINAL_ROUND_REST(a) \\").unwrap();
+    let rest: Vec<usize> = (0..5).filter(|word| !early.contains(word)).collect();
+    final_words(&mut src, &rest, &mut computed);
+    src.push('\n');
+
+    src
+}
+
+/// Emits the chi step for the given address words, along with the theta, rho
+/// and pi steps for those lanes of the first row that were not yet computed.
+fn final_words(src: &mut String, words: &[usize], computed: &mut [bool; 5]) {
+    for &word in words {
+        // address word `i` is 32-bit word `i + 3` of the state, i.e. one half
+        // of lane `(i + 3) / 2` of the first row
+        let x = (word + 3) / 2;
+        let lanes = [x, (x + 1) % 5, (x + 2) % 5];
+
+        for &x in &lanes {
+            if computed[x] {
+                continue;
+            }
+            computed[x] = true;
+
+            // lane `6x` moves to lane `x` in rho and pi
+            let (d, rho) = (x, RHO[6 * x]);
+            writeln!(
+                src,
+                "  ulong fd{d} = fc{} ^ ROL(fc{}, 1u); \\",
+                (d + 4) % 5,
+                (d + 1) % 5
+            )
+            .unwrap();
+            match rho {
+                0 => writeln!(src, "  ulong fb{x} = a[{}] ^ fd{d}; \\", 6 * x).unwrap(),
+                _ => {
+                    writeln!(src, "  ulong fb{x} = ROL(a[{}] ^ fd{d}, {rho}u); \\", 6 * x).unwrap()
+                }
+            }
+        }
+
+        let half = |x: usize| match (word + 3) % 2 {
+            0 => format!("(uint)fb{x}"),
+            _ => format!("(uint)(fb{x} >> 32)"),
+        };
+        writeln!(
+            src,
+            "  ((uint *)(a))[{}] = {} ^ (~{} & {}); \\",
+            word + 3,
+            half(lanes[0]),
+            half(lanes[1]),
+            half(lanes[2])
+        )
+        .unwrap();
+    }
+}
@@ -1,20 +1,43 @@
 use alloy_primitives::{hex, Address, FixedBytes};
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
-use console::Term;
-use fs4::FileExt;
+use calldata::Deployment;
 use itertools::chain;
-use ocl::{Buffer, Context, Device, MemFlags, Platform, ProQue, Program, Queue};
+use metrics::Metrics;
+use ocl::{enums::DeviceInfo, Buffer, Context, Device, MemFlags, Platform, ProQue, Queue};
+use output::{OutputFormat, Solution};
+use progress::{Progress, Reporter, RunSummary, Status};
 use rand::{thread_rng, Rng};
-use separator::Separatable;
+use sink::{FileSink, Hook, SolutionSink};
 use std::{
     fmt::Write as _,
-    fs::{File, OpenOptions},
-    io::prelude::*,
-    time::{SystemTime, UNIX_EPOCH},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use terminal_size::{terminal_size, Height};
 
+pub mod cache;
+pub mod calldata;
 pub mod cli;
+pub mod cpu;
+mod dashboard;
+pub mod estimate;
+pub mod export;
+pub mod jobs;
+pub mod keccak;
+pub mod metrics;
+pub mod output;
+pub mod progress;
+pub mod reference;
+pub mod results;
+pub mod scheduler;
+pub mod serve;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod store;
 
 const PROXY_CHILD_CODEHASH: [u8; 32] = [
     33, 195, 93, 190, 27, 52, 74, 36, 136, 207, 51, 33, 214, 206, 84, 47, 142, 159, 48, 85, 68,
@@ -26,13 +49,48 @@ const WORK_SIZE: u32 = 0x4000000; // max. 0x15400000 to abs. max 0xffffffff
 
 const WORK_FACTOR: u128 = (WORK_SIZE as u128) / 1_000_000;
 
+// only the lower three bytes of the upper nonce word make it into the salt
+const NONCE_SPACE: u32 = 1 << 24;
+
+// most candidates a work item may try per dispatch
+pub const MAX_LOOP_COUNT: u32 = 4096;
+
+// every init code hash adds a specialised CREATE2 round to the kernel
+const MAX_INIT_CODE_HASHES: usize = 16;
+
+// layout of the parameter buffer read by the runtime-parameterized kernel, in
+// 64-bit words
+const PARAM_LOOP_COUNT: usize = 0;
+const PARAM_INIT_CODE_HASHES: usize = 1;
+const PARAM_LEADING_ZEROES: usize = 2;
+const PARAM_TOTAL_ZEROES: usize = 3;
+const PARAM_CRITERION: usize = 4;
+const PARAM_CREATE3: usize = 5;
+const PARAM_MESSAGE_OFFSET: usize = 6;
+const PARAM_PATTERN: usize = 7;
+const PARAM_SEED: usize = PARAM_PATTERN + 5;
+const PARAM_CREATE2: usize = PARAM_SEED + 25;
+const PARAMS_LEN: usize = PARAM_CREATE2 + 25 * MAX_INIT_CODE_HASHES;
+
+// values of `PARAM_CRITERION`
+const CRITERION_LEADING: u64 = 0;
+const CRITERION_TOTAL: u64 = 1;
+const CRITERION_LEADING_AND_TOTAL: u64 = 2;
+const CRITERION_LEADING_OR_TOTAL: u64 = 3;
+const CRITERION_MATCHING: u64 = 4;
+
 static KERNEL_SRC: &str = include_str!("./kernels/keccak256.cl");
 
+#[derive(Clone)]
 pub enum CreateXVariant {
-    Create2 { init_code_hash: [u8; 32] },
+    /// Every guarded salt is tried against each of the init code hashes.
+    Create2 {
+        init_code_hashes: Vec<[u8; 32]>,
+    },
     Create3,
 }
 
+#[derive(Clone)]
 pub enum RewardVariant {
     LeadingZeros {
         zeros_threshold: u8,
@@ -53,6 +111,189 @@ pub enum RewardVariant {
     },
 }
 
+impl CreateXVariant {
+    fn description(&self) -> String {
+        match self {
+            CreateXVariant::Create2 { init_code_hashes } if init_code_hashes.len() > 1 => {
+                format!("Create2 ({} init code hashes)", init_code_hashes.len())
+            }
+            CreateXVariant::Create2 { .. } => "Create2".to_string(),
+            CreateXVariant::Create3 => "Create3".to_string(),
+        }
+    }
+}
+
+impl RewardVariant {
+    /// The criterion set with the `--leading`, `--total`, `--either` and
+    /// `--matching` options.
+    pub fn from_args(
+        zeros: Option<u8>,
+        total: Option<u8>,
+        either: bool,
+        pattern: Option<&str>,
+    ) -> Result<Self, &'static str> {
+        match (zeros, total, either, pattern) {
+            (Some(zeros), None, false, None) => Ok(RewardVariant::LeadingZeros {
+                zeros_threshold: zeros,
+            }),
+            (None, Some(total), false, None) => Ok(RewardVariant::TotalZeros {
+                zeros_threshold: total,
+            }),
+            (Some(zeros), Some(total), false, None) => Ok(RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold: zeros,
+                total_zeros_threshold: total,
+            }),
+            (Some(zeros), Some(total), true, None) => Ok(RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold: zeros,
+                total_zeros_threshold: total,
+            }),
+            (None, None, false, Some(pattern)) => {
+                let pattern: String = pattern
+                    .strip_prefix("0x")
+                    .unwrap_or(pattern)
+                    .chars()
+                    .map(|c| if c == 'X' { c } else { c.to_ascii_lowercase() })
+                    .collect();
+                Ok(RewardVariant::Matching {
+                    pattern: pattern.into_boxed_str(),
+                })
+            }
+            (None, None, _, None) => Err("a search criterion is required"),
+            (_, _, true, None) => Err("either requires both a leading and a total threshold"),
+            _ => Err("a pattern cannot be combined with zero byte thresholds"),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            RewardVariant::LeadingZeros { zeros_threshold } => {
+                format!("with {} leading zero byte(s)", zeros_threshold)
+            }
+            RewardVariant::TotalZeros { zeros_threshold } => {
+                format!("with {} total zero byte(s)", zeros_threshold)
+            }
+            RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => format!(
+                "with {} leading and {} total zero byte(s)",
+                leading_zeros_threshold, total_zeros_threshold
+            ),
+            RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => format!(
+                "with {} leading or {} total zero byte(s)",
+                leading_zeros_threshold, total_zeros_threshold
+            ),
+            RewardVariant::Matching { pattern } => {
+                format!("matching pattern 0x{}", pattern)
+            }
+        }
+    }
+
+    /// The probability of a random address meeting the criterion.
+    pub fn probability(&self) -> f64 {
+        // each byte is zero with probability 1/256
+        let leading = |zeros: u8| 256f64.powi(-(zeros as i32));
+        let total = |zeros: u8| at_least_zeros(20, zeros);
+        let both = |leading_zeros: u8, total_zeros: u8| {
+            leading(leading_zeros)
+                * at_least_zeros(
+                    20 - leading_zeros,
+                    total_zeros.saturating_sub(leading_zeros),
+                )
+        };
+
+        match self {
+            RewardVariant::LeadingZeros { zeros_threshold } => leading(*zeros_threshold),
+            RewardVariant::TotalZeros { zeros_threshold } => total(*zeros_threshold),
+            RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => both(*leading_zeros_threshold, *total_zeros_threshold),
+            RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => {
+                leading(*leading_zeros_threshold) + total(*total_zeros_threshold)
+                    - both(*leading_zeros_threshold, *total_zeros_threshold)
+            }
+            RewardVariant::Matching { pattern } => {
+                16f64.powi(-(pattern.chars().filter(|&c| c != 'X').count() as i32))
+            }
+        }
+    }
+
+    /// The criterion with each zero byte threshold raised by `by`, up to the
+    /// length of an address. Patterns are left as they are.
+    pub fn raised(&self, by: u8) -> RewardVariant {
+        let raise = |zeros: &u8| zeros.saturating_add(by).min(20);
+        match self {
+            RewardVariant::LeadingZeros { zeros_threshold } => RewardVariant::LeadingZeros {
+                zeros_threshold: raise(zeros_threshold),
+            },
+            RewardVariant::TotalZeros { zeros_threshold } => RewardVariant::TotalZeros {
+                zeros_threshold: raise(zeros_threshold),
+            },
+            RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold: raise(leading_zeros_threshold),
+                total_zeros_threshold: raise(total_zeros_threshold),
+            },
+            RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold: raise(leading_zeros_threshold),
+                total_zeros_threshold: raise(total_zeros_threshold),
+            },
+            RewardVariant::Matching { .. } => self.clone(),
+        }
+    }
+
+    /// The same kind of criterion, with thresholds that every address meets.
+    pub(crate) fn always_met(&self) -> RewardVariant {
+        match self {
+            RewardVariant::LeadingZeros { .. } => {
+                RewardVariant::LeadingZeros { zeros_threshold: 0 }
+            }
+            RewardVariant::TotalZeros { .. } => RewardVariant::TotalZeros { zeros_threshold: 0 },
+            RewardVariant::LeadingAndTotalZeros { .. } => RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold: 0,
+                total_zeros_threshold: 0,
+            },
+            RewardVariant::LeadingOrTotalZeros { .. } => RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold: 0,
+                total_zeros_threshold: 0,
+            },
+            RewardVariant::Matching { .. } => RewardVariant::Matching {
+                pattern: "X".repeat(40).into_boxed_str(),
+            },
+        }
+    }
+
+    /// The base-2 logarithm of the expected number of candidates per address
+    /// meeting the criterion.
+    pub fn difficulty_bits(&self) -> f64 {
+        -self.probability().log2()
+    }
+}
+
+/// The probability of at least `zeros` out of `bytes` random bytes being zero.
+fn at_least_zeros(bytes: u8, zeros: u8) -> f64 {
+    let p: f64 = 1.0 / 256.0;
+    (zeros..=bytes)
+        .map(|k| {
+            let binomial = (0..k).fold(1.0, |acc, i| acc * (bytes - i) as f64 / (i + 1) as f64);
+            binomial * p.powi(k as i32) * (1.0 - p).powi((bytes - k) as i32)
+        })
+        .sum()
+}
+
+#[derive(Clone)]
 pub enum SaltVariant {
     CrosschainSender {
         chain_id: [u8; 32],
@@ -67,6 +308,7 @@ pub enum SaltVariant {
     Random,
 }
 
+#[derive(Clone)]
 pub struct Config<'a> {
     pub gpu_device: u8,
     pub factory_address: [u8; 20],
@@ -74,6 +316,40 @@ pub struct Config<'a> {
     pub create_variant: CreateXVariant,
     pub reward: RewardVariant,
     pub output: &'a str,
+    /// Number of candidates each work item tries per dispatch.
+    pub loop_count: u32,
+    /// Whether to build the runtime-parameterized kernel (see
+    /// [`mk_generic_kernel_src`]) instead of one specialised for this config.
+    pub generic_kernel: bool,
+    /// Directory where compiled kernels are cached, if any.
+    pub kernel_cache: Option<PathBuf>,
+    /// Whether to check the kernel against the CPU reference before mining.
+    pub self_test: bool,
+    /// Format of the records written to the output file.
+    pub format: OutputFormat,
+    /// Whether to stop when the CPU reference disagrees with a solution
+    /// reported by the kernel, rather than skipping the solution.
+    pub halt_on_mismatch: bool,
+    /// How the mined contracts are deployed, for writing the CreateX calldata
+    /// along with each solution. Set with [`Config::set_deployments`].
+    pub deployments: Vec<Deployment>,
+    /// SQLite database every solution is also stored in. Requires the `sqlite`
+    /// feature.
+    pub database: Option<PathBuf>,
+    /// Command run in the background for every solution.
+    pub on_found: Option<Hook>,
+    /// How progress is reported.
+    pub progress: Progress,
+    /// Time between status records, when they are not shown on the screen.
+    pub status_interval: Duration,
+    /// Address to serve Prometheus metrics on, if any.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Flag which, once set, stops mining after the current kernel dispatch,
+    /// e.g. from a signal handler.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Summary of the run so far, kept up to date while mining for other
+    /// threads to read.
+    pub run_summary: Option<Arc<Mutex<RunSummary>>>,
 }
 
 impl<'a> Config<'a> {
@@ -82,40 +358,53 @@ impl<'a> Config<'a> {
         factory_address_str: &str,
         calling_address_str: Option<&str>,
         chain_id: Option<u64>,
-        init_code_hash: Option<&str>,
+        init_code_hashes: Option<&[&str]>,
         reward: RewardVariant,
         output: &'a str,
     ) -> Result<Self, &'static str> {
-        // convert main arguments from hex string to vector of bytes
-        let factory_address_vec =
-            hex::decode(factory_address_str).expect("could not decode factory address argument");
-        let calling_address_vec = calling_address_str.map(|calling_address| {
-            hex::decode(calling_address).expect("could not decode calling address argument")
-        });
-        let init_code_hash_vec = init_code_hash.map(|init_code_hash| {
-            hex::decode(init_code_hash).expect("could not decode init code hash argument")
-        });
-
-        // convert from vector to fixed array
-        let factory_address = TryInto::<[u8; 20]>::try_into(factory_address_vec)
-            .expect("invalid length for factory address argument");
-        let calling_address = calling_address_vec.map(|calling_address_vec| {
-            TryInto::<[u8; 20]>::try_into(calling_address_vec)
-                .expect("invalid length for calling address argument")
-        });
-        let init_code_hash = init_code_hash_vec.map(|init_code_hash_vec| {
-            TryInto::<[u8; 32]>::try_into(init_code_hash_vec)
-                .expect("invalid length for init code hash argument")
-        });
+        // convert main arguments from hex string to fixed arrays
+        let factory_address = hex::decode(factory_address_str)
+            .map_err(|_| "could not decode factory address argument")?
+            .try_into()
+            .map_err(|_| "invalid length for factory address argument")?;
+        let calling_address = calling_address_str
+            .map(|calling_address| {
+                hex::decode(calling_address)
+                    .map_err(|_| "could not decode calling address argument")?
+                    .try_into()
+                    .map_err(|_| "invalid length for calling address argument")
+            })
+            .transpose()?;
+        let init_code_hashes = init_code_hashes
+            .map(|init_code_hashes| {
+                init_code_hashes
+                    .iter()
+                    .map(|init_code_hash| {
+                        hex::decode(init_code_hash)
+                            .map_err(|_| "could not decode init code hash argument")?
+                            .try_into()
+                            .map_err(|_| "invalid length for init code hash argument")
+                    })
+                    .collect::<Result<Vec<[u8; 32]>, _>>()
+            })
+            .transpose()?;
         let chain_id = chain_id.map(|chain_id| {
             let mut arr = [0u8; 32];
             arr[24..].copy_from_slice(&chain_id.to_be_bytes());
             arr
         });
 
-        let create_variant = match init_code_hash {
-            Some(init_code_hash) => CreateXVariant::Create2 { init_code_hash },
-            None => CreateXVariant::Create3 {},
+        let create_variant = match init_code_hashes {
+            Some(init_code_hashes) => {
+                if init_code_hashes.is_empty() {
+                    return Err("at least one init code hash is required");
+                }
+                if init_code_hashes.len() > MAX_INIT_CODE_HASHES {
+                    return Err("at most 16 init code hashes can be mined for at once");
+                }
+                CreateXVariant::Create2 { init_code_hashes }
+            }
+            None => CreateXVariant::Create3,
         };
 
         match &reward {
@@ -205,27 +494,350 @@ impl<'a> Config<'a> {
             create_variant,
             reward,
             output,
+            loop_count: 1,
+            generic_kernel: false,
+            kernel_cache: cache::default_dir(),
+            self_test: true,
+            halt_on_mismatch: true,
+            format: OutputFormat::Text,
+            deployments: Vec::new(),
+            database: None,
+            on_found: None,
+            progress: Progress::Auto,
+            status_interval: Duration::from_secs(10),
+            metrics_addr: None,
+            stop: None,
+            run_summary: None,
         })
     }
+
+    /// Sets how the mined contracts are deployed, checking that the init code
+    /// of each deployment matches one of the init code hashes.
+    pub fn set_deployments(&mut self, deployments: Vec<Deployment>) -> Result<(), &'static str> {
+        calldata::validate(&deployments, &self.create_variant)?;
+        self.deployments = deployments;
+        Ok(())
+    }
+
+    /// Whether several contracts are mined for at once, in which case each
+    /// salt is noted along with the init code hash it belongs to.
+    pub fn multiple_init_code_hashes(&self) -> bool {
+        matches!(
+            self.create_variant,
+            CreateXVariant::Create2 { ref init_code_hashes } if init_code_hashes.len() > 1
+        )
+    }
+
+    /// The probability of a candidate salt meeting the criterion with the
+    /// given thresholds, for any of the init code hashes.
+    pub fn probability(&self, reward: &RewardVariant) -> f64 {
+        let hashes = match &self.create_variant {
+            CreateXVariant::Create2 { init_code_hashes } => init_code_hashes.len(),
+            CreateXVariant::Create3 => 1,
+        };
+        estimate::per_salt(reward.probability(), hashes)
+    }
+
+    /// The deployment of the contract with the init code hash at `index`.
+    pub fn deployment(&self, index: usize) -> Option<&Deployment> {
+        match self.create_variant {
+            CreateXVariant::Create2 {
+                ref init_code_hashes,
+            } => self
+                .deployments
+                .iter()
+                .find(|deployment| deployment.init_code_hash() == init_code_hashes[index]),
+            CreateXVariant::Create3 => self.deployments.first(),
+        }
+    }
+
+    /// Reconstructs the salt for a nonce reported by the kernel. The mined part
+    /// of the salt is the 4-byte message followed by the lower 7 bytes of the
+    /// nonce in little-endian order, i.e. the work item's global ID and the low
+    /// three bytes of `d_nonce * LOOP_COUNT + step`.
+    pub fn salt(&self, message: [u8; 4], nonce: u64) -> [u8; 32] {
+        let nonce = nonce.to_le_bytes();
+        let mined_salt = chain!(message, nonce[..7].iter().copied());
+
+        let salt: Vec<u8> = match self.salt_variant {
+            SaltVariant::CrosschainSender {
+                chain_id: _,
+                calling_address,
+            } => chain!(calling_address, [1u8], mined_salt).collect(),
+            SaltVariant::Crosschain { chain_id: _ } => {
+                chain!([0u8; 20], [1u8], mined_salt).collect()
+            }
+            SaltVariant::Sender { calling_address } => {
+                chain!(calling_address, [0u8], mined_salt).collect()
+            }
+            SaltVariant::Random => chain!(mined_salt, [0u8; 21]).collect(),
+        };
+
+        salt.try_into().unwrap()
+    }
+
+    /// Encodes the config into the parameter buffer of the runtime-parameterized
+    /// kernel. Switching the kernel to another config only takes writing its
+    /// parameters to the buffer, without recompiling.
+    pub fn params(&self) -> Vec<u64> {
+        let mut params = vec![0u64; PARAMS_LEN];
+
+        params[PARAM_LOOP_COUNT] = self.loop_count as u64;
+
+        let (leading, total, criterion) = match &self.reward {
+            RewardVariant::LeadingZeros { zeros_threshold } => {
+                (*zeros_threshold, 0, CRITERION_LEADING)
+            }
+            RewardVariant::TotalZeros { zeros_threshold } => (0, *zeros_threshold, CRITERION_TOTAL),
+            RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => (
+                *leading_zeros_threshold,
+                *total_zeros_threshold,
+                CRITERION_LEADING_AND_TOTAL,
+            ),
+            RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold,
+                total_zeros_threshold,
+            } => (
+                *leading_zeros_threshold,
+                *total_zeros_threshold,
+                CRITERION_LEADING_OR_TOTAL,
+            ),
+            RewardVariant::Matching { pattern } => {
+                for (i, (mask, value)) in pattern_masks(pattern).into_iter().enumerate() {
+                    params[PARAM_PATTERN + i] = mask as u64 | (value as u64) << 32;
+                }
+                (0, 0, CRITERION_MATCHING)
+            }
+        };
+        params[PARAM_LEADING_ZEROES] = leading as u64;
+        params[PARAM_TOTAL_ZEROES] = total as u64;
+        params[PARAM_CRITERION] = criterion;
+
+        let (seed, message_offset) = seed_template(&self.salt_variant);
+        params[PARAM_MESSAGE_OFFSET] = message_offset as u64;
+        write_lanes(&mut params[PARAM_SEED..], &seed);
+
+        let init_code_hashes = self.init_code_hashes();
+        params[PARAM_INIT_CODE_HASHES] = init_code_hashes.len() as u64;
+        params[PARAM_CREATE3] = matches!(self.create_variant, CreateXVariant::Create3) as u64;
+        for (k, init_code_hash) in init_code_hashes.iter().enumerate() {
+            let create2 = create2_template(&self.factory_address, init_code_hash);
+            write_lanes(&mut params[PARAM_CREATE2 + 25 * k..], &create2);
+        }
+
+        params
+    }
+
+    /// The init code hashes that go into the CREATE2 hash, which is that of the
+    /// CreateX proxy for CREATE3 deployments.
+    fn init_code_hashes(&self) -> Vec<[u8; 32]> {
+        match self.create_variant {
+            CreateXVariant::Create2 {
+                ref init_code_hashes,
+            } => init_code_hashes.clone(),
+            CreateXVariant::Create3 => vec![PROXY_CHILD_CODEHASH],
+        }
+    }
+
+    /// Describes the resolved config, including which bytes of the salt are
+    /// fixed and which are mined.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+
+        let salt_variant = match self.salt_variant {
+            SaltVariant::CrosschainSender { .. } => "permissioned, crosschain protected",
+            SaltVariant::Crosschain { .. } => "crosschain protected",
+            SaltVariant::Sender { .. } => "permissioned",
+            SaltVariant::Random => "unprotected",
+        };
+        let chain_id = match self.salt_variant {
+            SaltVariant::CrosschainSender { chain_id, .. }
+            | SaltVariant::Crosschain { chain_id } => {
+                alloy_primitives::U256::from_be_bytes(chain_id).to_string()
+            }
+            _ => "none".to_string(),
+        };
+
+        // the message and nonce bytes are mined, everything else is fixed
+        let mined = match self.salt_variant {
+            SaltVariant::Random => 0,
+            _ => 21,
+        };
+        let mut salt_layout = hex::encode(self.salt([0; 4], 0));
+        salt_layout.replace_range(2 * mined..2 * mined + 8, "MMMMMMMM");
+        salt_layout.replace_range(2 * mined + 8..2 * mined + 22, "NNNNNNNNNNNNNN");
+
+        writeln!(
+            summary,
+            "factory:          0x{}",
+            hex::encode(self.factory_address)
+        )
+        .unwrap();
+        writeln!(summary, "salt protection:  {}", salt_variant).unwrap();
+        writeln!(summary, "chain ID:         {}", chain_id).unwrap();
+        writeln!(summary, "salt layout:      0x{}", salt_layout).unwrap();
+        writeln!(
+            summary,
+            "                  (M = random message, N = nonce in little-endian order)"
+        )
+        .unwrap();
+        writeln!(
+            summary,
+            "deployment:       {}",
+            self.create_variant.description()
+        )
+        .unwrap();
+        if let CreateXVariant::Create2 { init_code_hashes } = &self.create_variant {
+            for init_code_hash in init_code_hashes {
+                writeln!(
+                    summary,
+                    "init code hash:   0x{}",
+                    hex::encode(init_code_hash)
+                )
+                .unwrap();
+            }
+        }
+        for deployment in &self.deployments {
+            writeln!(
+                summary,
+                "calldata for:     {}",
+                deployment.signature(&self.create_variant)
+            )
+            .unwrap();
+        }
+        writeln!(summary, "criterion:        {}", self.reward.description()).unwrap();
+        writeln!(summary, "GPU device:       {}", self.gpu_device).unwrap();
+        writeln!(summary, "loop count:       {}", self.loop_count).unwrap();
+        writeln!(
+            summary,
+            "kernel:           {}",
+            match self.generic_kernel {
+                true => "runtime-parameterized",
+                false => "specialised",
+            }
+        )
+        .unwrap();
+        writeln!(
+            summary,
+            "kernel cache:     {}",
+            match &self.kernel_cache {
+                Some(dir) => dir.display().to_string(),
+                None => "disabled".to_string(),
+            }
+        )
+        .unwrap();
+        write!(summary, "output:           {}", self.output).unwrap();
+        if let Some(database) = &self.database {
+            write!(summary, "\ndatabase:         {}", database.display()).unwrap();
+        }
+        write!(
+            summary,
+            "\nprogress:         {}",
+            format!("{:?}", self.progress).to_lowercase()
+        )
+        .unwrap();
+        if let Some(hook) = &self.on_found {
+            write!(
+                summary,
+                "\non found:         {} (timeout {}s)",
+                hook.command,
+                hook.timeout.as_secs()
+            )
+            .unwrap();
+        }
+        if let Some(addr) = &self.metrics_addr {
+            write!(summary, "\nmetrics:          http://{}/metrics", addr).unwrap();
+        }
+
+        summary
+    }
+}
+
+/// Mines with the config, writing solutions to its output file and, if set,
+/// its database, until stopped.
+pub fn gpu(config: Config) -> ocl::Result<RunSummary> {
+    // (create if necessary) and open a file where found salts will be written
+    let file = FileSink::for_config(&config)
+        .map_err(|e| format!("Could not create or open {} file: {}", config.output, e))?;
+    let mut sinks: Vec<Box<dyn SolutionSink>> = vec![Box::new(file)];
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &config.database {
+        sinks.push(Box::new(
+            store::Store::open(path).map_err(|e| e.to_string())?,
+        ));
+    }
+    #[cfg(not(feature = "sqlite"))]
+    if config.database.is_some() {
+        return Err("storing solutions in a database requires the sqlite feature".into());
+    }
+
+    if let Some(hook) = &config.on_found {
+        sinks.push(Box::new(hook.sink()));
+    }
+
+    gpu_with_sinks(config, sinks)
+}
+
+/// Mines with the config, handing every solution to each of the sinks, until
+/// stopped.
+pub fn gpu_with_sinks(
+    config: Config,
+    mut sinks: Vec<Box<dyn SolutionSink>>,
+) -> ocl::Result<RunSummary> {
+    // set up a controller for progress output
+    let mut reporter = Reporter::new(config.progress, config.status_interval)?;
+
+    let mut summary = None;
+    let result = mine(config, &mut sinks, &mut reporter, &mut summary);
+
+    let finished = sinks.iter_mut().try_for_each(|sink| {
+        sink.finish()
+            .map_err(|e| format!("Could not finish writing solutions: {}", e))
+    });
+
+    // report what was mined, even if the run ended in an error
+    if let Some(summary) = &summary {
+        reporter.summary(summary);
+    }
+    result?;
+    finished?;
+
+    Ok(summary.unwrap_or_default())
 }
 
 /// Adapted from https://github.com/0age/create2crunch
 ///
-pub fn gpu(config: Config) -> ocl::Result<()> {
-    println!(
+/// Mines until stopped, keeping `summary` up to date from the moment mining
+/// starts.
+fn mine(
+    config: Config,
+    sinks: &mut [Box<dyn SolutionSink>],
+    reporter: &mut Reporter,
+    summary: &mut Option<RunSummary>,
+) -> ocl::Result<()> {
+    reporter.message(&format!(
         "Setting up OpenCL miner using device {}...",
         config.gpu_device
-    );
-
-    // (create if necessary) and open a file where found salts will be written
-    let file = output_file(&config);
+    ));
 
     // track how many addresses have been found and information about them
     let mut found: u64 = 0;
     let mut found_list: Vec<String> = vec![];
 
-    // set up a controller for terminal output
-    let term = Term::stdout();
+    // what was asked for on the dashboard, if shown
+    let mut controls;
+
+    // whether the run was asked to stop, e.g. by a signal
+    let stopped = || {
+        config
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::SeqCst))
+    };
 
     // set up a platform to use
     let platform = Platform::new(ocl::core::default_platform()?);
@@ -239,20 +851,44 @@ pub fn gpu(config: Config) -> ocl::Result<()> {
         .devices(device)
         .build()?;
 
-    // set up the program to use
-    let program = Program::builder()
-        .devices(device)
-        .src(mk_kernel_src(&config))
-        .build(&context)?;
-
-    // set up the queue to use
-    let queue = Queue::new(&context, device, None)?;
-
-    // set up the "proqueue" (or amalgamation of various elements) to use
-    let ocl_pq = ProQue::new(context, queue, program, Some(WORK_SIZE));
-
-    // create a random number generator
-    let mut rng = thread_rng();
+    // pick the kernel specialisation for the device
+    let device_name = device.name()?;
+    let kernel_platform = KernelPlatform::detect(device)?;
+
+    // serve the metrics of the run, if asked to
+    let metrics = match config.metrics_addr {
+        Some(addr) => Some(
+            Metrics::serve(addr, &config, &device_name)
+                .map_err(|e| format!("Could not serve metrics on {}: {}", addr, e))?,
+        ),
+        None => None,
+    };
+    reporter.message(&format!(
+        "Using the {} kernel specialisation for {}.",
+        kernel_platform.name(),
+        device_name
+    ));
+
+    // set up the kernel and its buffers
+    let mut dispatcher = Dispatcher::new(
+        &config,
+        &context,
+        device,
+        &kernel_platform.build_options(),
+        &mut |note| reporter.notice(note),
+    )?;
+
+    if config.self_test {
+        reporter.message("Checking the kernel against the CPU reference...");
+        self_test(
+            &config,
+            &dispatcher,
+            &context,
+            device,
+            &kernel_platform.build_options(),
+            &mut |note| reporter.notice(note),
+        )?;
+    }
 
     // determine the start time
     let start_time: f64 = SystemTime::now()
@@ -262,324 +898,719 @@ pub fn gpu(config: Config) -> ocl::Result<()> {
 
     // set up variables for tracking performance
     let mut rate: f64 = 0.0;
-    let mut cumulative_nonce: u64 = 0;
-
-    // the previous timestamp of printing to the terminal
-    let mut previous_time: f64 = 0.0;
-
-    // the last work duration in milliseconds
-    let mut work_duration_millis: u64 = 0;
+    let summary = summary.insert(RunSummary::default());
 
     // begin searching for addresses
     loop {
-        // construct the 4-byte message to hash, leaving last 8 of salt empty
-        let salt = FixedBytes::<4>::random();
+        // bring the summary up to date, now that the previous dispatch has
+        // finished
+        let runtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64()
+            - start_time;
+        summary.runtime = Duration::from_secs_f64(runtime.max(0.0));
+        summary.hashes = dispatcher.candidates() * dispatcher.dispatches;
+        if let Some(run_summary) = &config.run_summary {
+            *run_summary.lock().unwrap() = summary.clone();
+        }
+
+        // wait while mining is paused, and stop when asked to
+        controls = reporter.poll()?;
+        while controls.paused && !controls.quit && !stopped() {
+            std::thread::sleep(Duration::from_millis(100));
+            controls = reporter.poll()?;
+        }
+        if controls.quit || stopped() {
+            return Ok(());
+        }
+
+        dispatcher.enqueue()?;
+
+        // we don't want to print too fast
+        if reporter.status_due() {
+            // get the total runtime
+            let current_time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as f64;
+            let total_runtime = current_time - start_time;
+
+            // determine the number of attempts being made per second
+            let work_rate: u128 =
+                WORK_FACTOR * config.loop_count as u128 * dispatcher.dispatches as u128;
+            if total_runtime > 0.0 {
+                rate = 1.0 / total_runtime;
+            }
+
+            let reward = config.reward.raised(controls.raise);
+            let status = Status {
+                device: device_name.clone(),
+                runtime: Duration::from_secs_f64(total_runtime.max(0.0)),
+                cycles: dispatcher.dispatches,
+                work_size: dispatcher.candidates(),
+                rate: work_rate as f64 * rate,
+                attempts: work_rate as f64,
+                probability: config.probability(&reward),
+                found,
+                search_space: dispatcher.search_space(),
+                variant: config.create_variant.description(),
+                threshold: reward.description(),
+            };
+            reporter.status(&status, &found_list)?;
+        }
+
+        let candidate = dispatcher.collect(&config)?;
+        if let Some(metrics) = &metrics {
+            metrics.dispatch(dispatcher.candidates(), dispatcher.last_duration);
+        }
 
-        // build a corresponding buffer for passing the message to the kernel
+        let Some(candidate) = candidate else {
+            continue;
+        };
+
+        if let Err(mismatch) = candidate.verify(&config) {
+            if config.halt_on_mismatch {
+                return Err(format!("Kernel verification failed: {mismatch}").into());
+            }
+            reporter.notice(&format!("MISMATCH: {mismatch}"));
+            continue;
+        }
+
+        // skip solutions below a threshold raised on the dashboard
+        if !reference::meets_criterion(&config.reward.raised(controls.raise), &candidate.address) {
+            continue;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let solution = Solution::new(
+            &config,
+            candidate.salt,
+            candidate.address,
+            candidate.index,
+            &device_name,
+            timestamp,
+        );
+
+        let output = solution.text(config.multiple_init_code_hashes());
+
+        let show = format!(
+            "{output} ({} / {})",
+            solution.leading_zeros, solution.total_zeros
+        );
+        let show = match config.reward {
+            RewardVariant::Matching { pattern: _ } => output,
+            _ => show,
+        };
+        reporter.solution(&solution, &show);
+        if let Some(metrics) = &metrics {
+            metrics.solution(&solution);
+        }
+        summary.add(&solution);
+        found_list.push(show);
+
+        for sink in sinks.iter_mut() {
+            sink.write(&solution)
+                .map_err(|e| format!("Could not write solution: {}", e))?;
+        }
+        found += 1;
+    }
+}
+
+/// A salt reported by a kernel dispatch, along with the address it yields and
+/// the index of the init code hash it was found for.
+pub(crate) struct Candidate {
+    pub salt: [u8; 32],
+    pub address: [u8; 20],
+    pub index: usize,
+}
+
+impl Candidate {
+    /// Double-checks the candidate on the CPU, since a miscompiled kernel
+    /// would otherwise go unnoticed.
+    pub fn verify(&self, config: &Config) -> Result<(), String> {
+        let expected = reference::address(config, self.salt, self.index);
+        if self.address != expected || !reference::meets_criterion(&config.reward, &expected) {
+            return Err(format!(
+                "the GPU reported 0x{} for salt 0x{}, but the CPU reference computes 0x{}",
+                hex::encode(self.address),
+                hex::encode(self.salt),
+                hex::encode(expected)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The kernel for a config on a device, along with the buffers it is
+/// dispatched with.
+pub(crate) struct Dispatcher {
+    ocl_pq: ProQue,
+    params_buffer: Option<Buffer<u64>>,
+    loop_count: u32,
+    /// The 4-byte message hashed with the nonces, leaving the last 8 bytes of
+    /// the salt empty. A new one is drawn whenever a salt is found or the
+    /// nonce space is exhausted.
+    message: FixedBytes<4>,
+    message_buffer: Buffer<u8>,
+    nonce: [u32; 1],
+    nonce_buffer: Buffer<u32>,
+    solutions: Vec<u64>,
+    solutions_buffer: Buffer<u64>,
+    /// Whether to draw a new message before the next dispatch.
+    start_over: bool,
+    /// When the current dispatch was enqueued.
+    enqueued: Instant,
+    /// Number of kernel dispatches so far.
+    pub dispatches: u64,
+    /// Time taken by the last dispatch.
+    pub last_duration: Duration,
+}
+
+impl Dispatcher {
+    /// Builds the kernel for the config, with its own queue on the device.
+    /// Problems with the kernel cache are handed to `notice`.
+    pub fn new(
+        config: &Config,
+        context: &Context,
+        device: Device,
+        build_options: &str,
+        notice: &mut dyn FnMut(&str),
+    ) -> ocl::Result<Self> {
+        // set up the program to use
+        let src = kernel_src(config);
+        let program = cache::build_program(
+            context,
+            device,
+            &src,
+            build_options,
+            config.kernel_cache.as_deref(),
+            notice,
+        )
+        .map_err(|e| annotate_build_log(&src, &e.to_string()))?;
+
+        // set up the queue to use
+        let queue = Queue::new(context, device, None)?;
+
+        // set up the "proqueue" (or amalgamation of various elements) to use
+        let ocl_pq = ProQue::new(context.clone(), queue, program, Some(WORK_SIZE));
+
+        // the runtime-parameterized kernel reads the config from a buffer
+        let params_buffer = match config.generic_kernel {
+            true => Some(
+                Buffer::builder()
+                    .queue(ocl_pq.queue().clone())
+                    .flags(MemFlags::new().read_only())
+                    .len(PARAMS_LEN)
+                    .copy_host_slice(&config.params())
+                    .build()?,
+            ),
+            false => None,
+        };
+
+        let message = FixedBytes::<4>::random();
         let message_buffer = Buffer::builder()
             .queue(ocl_pq.queue().clone())
             .flags(MemFlags::new().read_only())
             .len(4)
-            .copy_host_slice(&salt[..])
+            .copy_host_slice(&message[..])
             .build()?;
-
-        // reset nonce & create a buffer to view it in little-endian
-        // for more uniformly distributed nonces, we shall initialize it to a random value
-        let mut nonce: [u32; 1] = rng.gen();
-        let mut view_buf = [0; 8];
-
-        // build a corresponding buffer for passing the nonce to the kernel
-        let mut nonce_buffer = Buffer::builder()
+        let nonce_buffer = Buffer::builder()
             .queue(ocl_pq.queue().clone())
             .flags(MemFlags::new().read_only())
             .len(1)
-            .copy_host_slice(&nonce)
+            .copy_host_slice(&[0])
             .build()?;
-
-        // establish a buffer for nonces that result in desired addresses
-        let mut solutions: Vec<u64> = vec![0; 4];
         let solutions_buffer = Buffer::builder()
             .queue(ocl_pq.queue().clone())
             .flags(MemFlags::new().write_only())
+            .len(5)
+            .copy_host_slice(&[0; 5])
+            .build()?;
+
+        Ok(Self {
+            ocl_pq,
+            params_buffer,
+            loop_count: config.loop_count,
+            message,
+            message_buffer,
+            nonce: [0],
+            nonce_buffer,
+            solutions: vec![0; 5],
+            solutions_buffer,
+            start_over: true,
+            enqueued: Instant::now(),
+            dispatches: 0,
+            last_duration: Duration::ZERO,
+        })
+    }
+
+    /// Switches the runtime-parameterized kernel over to the config with just
+    /// a write to its parameter buffer, and starts over with a new message.
+    pub fn set_params(&mut self, config: &Config) -> ocl::Result<()> {
+        let Some(params_buffer) = &self.params_buffer else {
+            return Err("only the generic kernel can be switched to another config".into());
+        };
+        params_buffer.write(&config.params()).enq()?;
+
+        self.loop_count = config.loop_count;
+        self.start_over = true;
+        // the last dispatch says nothing about how long the next one takes
+        self.last_duration = Duration::ZERO;
+
+        Ok(())
+    }
+
+    /// Candidates tried per dispatch.
+    pub fn candidates(&self) -> u64 {
+        WORK_SIZE as u64 * self.loop_count as u64
+    }
+
+    fn nonce_limit(&self) -> u32 {
+        NONCE_SPACE / self.loop_count
+    }
+
+    /// Draws a new message, and a random nonce to start from.
+    fn start_over(&mut self) -> ocl::Result<()> {
+        self.message = FixedBytes::<4>::random();
+        self.message_buffer = Buffer::builder()
+            .queue(self.ocl_pq.queue().clone())
+            .flags(MemFlags::new().read_only())
             .len(4)
-            .copy_host_slice(&solutions)
+            .copy_host_slice(&self.message[..])
             .build()?;
 
-        // repeatedly enqueue kernel to search for new addresses
-        loop {
-            // build the kernel and define the type of each buffer
-            let kern = ocl_pq
-                .kernel_builder("hashMessage")
-                .arg_named("message", None::<&Buffer<u8>>)
-                .arg_named("nonce", None::<&Buffer<u32>>)
-                .arg_named("solutions", None::<&Buffer<u64>>)
-                .build()?;
-
-            // set each buffer
-            kern.set_arg("message", Some(&message_buffer))?;
-            kern.set_arg("nonce", Some(&nonce_buffer))?;
-            kern.set_arg("solutions", &solutions_buffer)?;
-
-            // enqueue the kernel
-            unsafe { kern.enq()? };
-
-            // calculate the current time
-            let mut now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-            let current_time = now.as_secs() as f64;
-
-            // we don't want to print too fast
-            let print_output = current_time - previous_time > 0.99;
-            previous_time = current_time;
-
-            // clear the terminal screen
-            if print_output {
-                term.clear_screen()?;
-
-                // get the total runtime and parse into hours : minutes : seconds
-                let total_runtime = current_time - start_time;
-                let total_runtime_hrs = total_runtime as u64 / 3600;
-                let total_runtime_mins = (total_runtime as u64 - total_runtime_hrs * 3600) / 60;
-                let total_runtime_secs = total_runtime
-                    - (total_runtime_hrs * 3600) as f64
-                    - (total_runtime_mins * 60) as f64;
-
-                // determine the number of attempts being made per second
-                let work_rate: u128 = WORK_FACTOR * cumulative_nonce as u128;
-                if total_runtime > 0.0 {
-                    rate = 1.0 / total_runtime;
-                }
+        // for more uniformly distributed nonces, we shall initialize it to a
+        // random value
+        self.nonce = [thread_rng().gen_range(0..self.nonce_limit())];
+        self.nonce_buffer = Buffer::builder()
+            .queue(self.ocl_pq.queue().clone())
+            .flags(MemFlags::new().read_only())
+            .len(1)
+            .copy_host_slice(&self.nonce)
+            .build()?;
 
-                // fill the buffer for viewing the properly-formatted nonce
-                LittleEndian::write_u64(&mut view_buf, (nonce[0] as u64) << 32);
-
-                // calculate the terminal height, defaulting to a height of ten rows
-                let height = terminal_size().map(|(_w, Height(h))| h).unwrap_or(10);
-
-                // display information about the total runtime and work size
-                term.write_line(&format!(
-                    "total runtime: {}:{:02}:{:02} ({} cycles)\t\t\t\
-                     work size per cycle: {}",
-                    total_runtime_hrs,
-                    total_runtime_mins,
-                    total_runtime_secs,
-                    cumulative_nonce,
-                    WORK_SIZE.separated_string(),
-                ))?;
-
-                // display information about the attempt rate and found solutions
-                term.write_line(&format!(
-                    "rate: {:.2} million attempts per second\t\t\t\
-                     total found this run: {}",
-                    work_rate as f64 * rate,
-                    found
-                ))?;
-
-                let threshold_string = match config.reward {
-                    RewardVariant::LeadingZeros { zeros_threshold } => {
-                        format!("with {} leading zero byte(s)", zeros_threshold)
-                    }
-                    RewardVariant::TotalZeros { zeros_threshold } => {
-                        format!("with {} total zero byte(s)", zeros_threshold)
-                    }
-                    RewardVariant::LeadingAndTotalZeros {
-                        leading_zeros_threshold,
-                        total_zeros_threshold,
-                    } => format!(
-                        "with {} leading and {} total zero byte(s)",
-                        leading_zeros_threshold, total_zeros_threshold
-                    ),
-                    RewardVariant::LeadingOrTotalZeros {
-                        leading_zeros_threshold,
-                        total_zeros_threshold,
-                    } => format!(
-                        "with {} leading or {} total zero byte(s)",
-                        leading_zeros_threshold, total_zeros_threshold
-                    ),
-                    RewardVariant::Matching { ref pattern } => {
-                        format!("matching pattern 0x{}", pattern)
-                    }
-                };
+        // establish a buffer for nonces that result in desired addresses
+        // (the fifth slot holds the index of the matching init code hash)
+        self.solutions = vec![0; 5];
+        self.solutions_buffer = Buffer::builder()
+            .queue(self.ocl_pq.queue().clone())
+            .flags(MemFlags::new().write_only())
+            .len(5)
+            .copy_host_slice(&self.solutions)
+            .build()?;
 
-                let variant = match config.create_variant {
-                    CreateXVariant::Create2 { init_code_hash: _ } => "Create2",
-                    CreateXVariant::Create3 {} => "Create3",
-                };
+        self.start_over = false;
+        Ok(())
+    }
 
-                // display information about the current search criteria
-                term.write_line(&format!(
-                    "current search space: {}xxxxxxxx{:06x}\t\t\
-                     threshold: mining for {} address {}",
-                    hex::encode(salt),
-                    // Only the first 3 bytes are used
-                    BigEndian::read_u64(&view_buf) >> 8,
-                    variant,
-                    threshold_string
-                ))?;
-
-                // display recently found solutions based on terminal height
-                let rows = if height < 5 { 1 } else { height as usize - 4 };
-                let last_rows: Vec<String> = found_list.iter().cloned().rev().take(rows).collect();
-                let ordered: Vec<String> = last_rows.iter().cloned().rev().collect();
-                let recently_found = &ordered.join("\n");
-                term.write_line(recently_found)?;
-            }
+    /// Enqueues the kernel for the next nonce.
+    pub fn enqueue(&mut self) -> ocl::Result<()> {
+        if self.start_over {
+            self.start_over()?;
+        }
 
-            // increment the cumulative nonce (does not reset after a match)
-            cumulative_nonce += 1;
+        // build the kernel and define the type of each buffer
+        let mut kern_builder = self.ocl_pq.kernel_builder("hashMessage");
+        kern_builder
+            .arg_named("message", None::<&Buffer<u8>>)
+            .arg_named("nonce", None::<&Buffer<u32>>)
+            .arg_named("solutions", None::<&Buffer<u64>>);
+        if self.params_buffer.is_some() {
+            kern_builder.arg_named("params", None::<&Buffer<u64>>);
+        }
+        let kern = kern_builder.build()?;
+
+        // set each buffer
+        kern.set_arg("message", Some(&self.message_buffer))?;
+        kern.set_arg("nonce", Some(&self.nonce_buffer))?;
+        kern.set_arg("solutions", &self.solutions_buffer)?;
+        if let Some(params_buffer) = &self.params_buffer {
+            kern.set_arg("params", params_buffer)?;
+        }
 
-            // record the start time of the work
-            let work_start_time_millis = now.as_secs() * 1000 + now.subsec_nanos() as u64 / 1000000;
+        // enqueue the kernel
+        unsafe { kern.enq()? };
+        self.enqueued = Instant::now();
 
-            // sleep for 98% of the previous work duration to conserve CPU
-            if work_duration_millis != 0 {
-                std::thread::sleep(std::time::Duration::from_millis(
-                    work_duration_millis * 980 / 1000,
-                ));
-            }
+        Ok(())
+    }
+
+    /// Waits for the enqueued dispatch to finish, returning the salt it
+    /// found, if any.
+    pub fn collect(&mut self, config: &Config) -> ocl::Result<Option<Candidate>> {
+        // sleep for 98% of the previous work duration to conserve CPU
+        if !self.last_duration.is_zero() {
+            std::thread::sleep(self.last_duration * 98 / 100);
+        }
+
+        // read the solutions from the device
+        self.solutions_buffer.read(&mut self.solutions).enq()?;
+        self.last_duration = self.enqueued.elapsed();
+        self.dispatches += 1;
+
+        if self.solutions[0] != 0 {
+            self.start_over = true;
+
+            // get the address that results from the hash
+            let mut address = [0; 20];
+            address[..8].copy_from_slice(&self.solutions[1].to_be_bytes());
+            address[8..16].copy_from_slice(&self.solutions[2].to_be_bytes());
+            address[16..].copy_from_slice(&self.solutions[3].to_be_bytes()[..4]);
+
+            return Ok(Some(Candidate {
+                salt: config.salt(self.message.0, self.solutions[0]),
+                address,
+                index: self.solutions[4] as usize,
+            }));
+        }
+
+        // if no solution has yet been found, increment the nonce
+        self.nonce[0] += 1;
 
-            // read the solutions from the device
-            solutions_buffer.read(&mut solutions).enq()?;
+        // start over with a new message once the nonce space is exhausted
+        if self.nonce[0] >= self.nonce_limit() {
+            self.start_over = true;
+            return Ok(None);
+        }
+
+        // update the nonce buffer with the incremented nonce value
+        self.nonce_buffer = Buffer::builder()
+            .queue(self.ocl_pq.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(1)
+            .copy_host_slice(&self.nonce)
+            .build()?;
+
+        Ok(None)
+    }
 
-            // record the end time of the work and compute how long the work took
-            now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-            work_duration_millis = (now.as_secs() * 1000 + now.subsec_nanos() as u64 / 1000000)
-                - work_start_time_millis;
+    /// The part of the salt space being searched, for the status display.
+    pub fn search_space(&self) -> String {
+        // fill the buffer for viewing the properly-formatted nonce
+        let mut view_buf = [0; 8];
+        let upper_nonce = self.nonce[0] * self.loop_count;
+        LittleEndian::write_u64(&mut view_buf, (upper_nonce as u64) << 32);
+
+        format!(
+            "{}xxxxxxxx{:06x}",
+            hex::encode(self.message),
+            // Only the first 3 bytes are used
+            BigEndian::read_u64(&view_buf) >> 8,
+        )
+    }
+}
 
-            // if at least one solution is found, end the loop
-            if solutions[0] != 0 {
-                break;
+/// Measures the rate of the kernel for the config by running it for about
+/// `duration`, in millions of candidates per second.
+pub fn benchmark(config: &Config, duration: Duration) -> ocl::Result<f64> {
+    let platform = Platform::new(ocl::core::default_platform()?);
+    let device = Device::by_idx_wrap(platform, config.gpu_device as usize)?;
+    let context = Context::builder()
+        .platform(platform)
+        .devices(device)
+        .build()?;
+    let kernel_platform = KernelPlatform::detect(device)?;
+    let mut dispatcher = Dispatcher::new(
+        config,
+        &context,
+        device,
+        &kernel_platform.build_options(),
+        &mut |note| eprintln!("{note}"),
+    )?;
+
+    // the first dispatch is not timed, as it includes setting up the kernel
+    let mut dispatches: u64 = 0;
+    let mut start = None;
+    loop {
+        dispatcher.enqueue()?;
+        dispatcher.collect(config)?;
+
+        match start {
+            None => start = Some(Instant::now()),
+            Some(start) => {
+                dispatches += 1;
+                if start.elapsed() >= duration {
+                    break;
+                }
             }
+        }
+    }
+
+    let elapsed = start.map_or(0.0, |start| start.elapsed().as_secs_f64());
+    if dispatches == 0 || elapsed == 0.0 {
+        return Err("The benchmark finished too early to measure a rate".into());
+    }
 
-            // if no solution has yet been found, increment the nonce
-            nonce[0] += 1;
+    Ok((dispatcher.candidates() * dispatches) as f64 / elapsed / 1e6)
+}
+
+/// Runs the kernel mined with for a few nonces, with the thresholds of its
+/// criterion lowered until every address meets them, and checks the reported
+/// addresses against the CPU reference. The generic kernel takes the
+/// thresholds as parameters, so the program of the dispatcher is run as built.
+/// A specialised kernel has them built in, so a copy of it differing only in
+/// the thresholds is built, through the kernel cache, down to its loop count
+/// and the address words it computes before checking the criterion.
+pub(crate) fn self_test(
+    config: &Config,
+    dispatcher: &Dispatcher,
+    context: &Context,
+    device: Device,
+    build_options: &str,
+    notice: &mut dyn FnMut(&str),
+) -> ocl::Result<()> {
+    let test_config = Config {
+        reward: config.reward.always_met(),
+        ..config.clone()
+    };
 
-            // update the nonce buffer with the incremented nonce value
-            nonce_buffer = Buffer::builder()
-                .queue(ocl_pq.queue().clone())
-                .flags(MemFlags::new().read_write())
-                .len(1)
-                .copy_host_slice(&nonce)
-                .build()?;
+    let built;
+    let ocl_pq = match config.generic_kernel {
+        true => &dispatcher.ocl_pq,
+        false => {
+            let src = mk_self_test_kernel_src(config);
+            let program = cache::build_program(
+                context,
+                device,
+                &src,
+                build_options,
+                test_config.kernel_cache.as_deref(),
+                notice,
+            )
+            .map_err(|e| annotate_build_log(&src, &e.to_string()))?;
+            let queue = Queue::new(context, device, None)?;
+            built = ProQue::new(context.clone(), queue, program, Some(1));
+            &built
         }
+    };
 
-        let solution = solutions[0];
-        let solution = solution.to_le_bytes();
+    let message = FixedBytes::<4>::random();
+    let message_buffer = Buffer::builder()
+        .queue(ocl_pq.queue().clone())
+        .flags(MemFlags::new().read_only())
+        .len(4)
+        .copy_host_slice(&message[..])
+        .build()?;
+    let params = test_config.params();
+    let params_buffer = Buffer::builder()
+        .queue(ocl_pq.queue().clone())
+        .flags(MemFlags::new().read_only())
+        .len(PARAMS_LEN)
+        .copy_host_slice(&params)
+        .build()?;
 
-        let mined_salt = chain!(salt, solution[..7].iter().copied());
+    let loop_count = test_config.loop_count;
+    let hashes = match &test_config.create_variant {
+        CreateXVariant::Create2 { init_code_hashes } => init_code_hashes.len(),
+        CreateXVariant::Create3 => 1,
+    };
+    for nonce in [0, 1, thread_rng().gen_range(2..NONCE_SPACE / loop_count)] {
+        let nonce_buffer = Buffer::builder()
+            .queue(ocl_pq.queue().clone())
+            .flags(MemFlags::new().read_only())
+            .len(1)
+            .copy_host_slice(&[nonce])
+            .build()?;
+        // a value the kernel never reports, so that reporting nothing fails
+        let mut solutions: Vec<u64> = vec![u64::MAX; 5];
+        let solutions_buffer = Buffer::builder()
+            .queue(ocl_pq.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(5)
+            .copy_host_slice(&solutions)
+            .build()?;
 
-        let salt: Vec<u8> = match config.salt_variant {
-            SaltVariant::CrosschainSender {
-                chain_id: _,
-                calling_address,
-            } => chain!(calling_address, [1u8], mined_salt).collect(),
-            SaltVariant::Crosschain { chain_id: _ } => {
-                chain!([0u8; 20], [1u8], mined_salt).collect()
-            }
-            SaltVariant::Sender { calling_address } => {
-                chain!(calling_address, [0u8], mined_salt).collect()
-            }
-            SaltVariant::Random => chain!(mined_salt, [0u8; 21]).collect(),
-        };
+        let mut kern_builder = ocl_pq.kernel_builder("hashMessage");
+        kern_builder
+            .arg_named("message", None::<&Buffer<u8>>)
+            .arg_named("nonce", None::<&Buffer<u32>>)
+            .arg_named("solutions", None::<&Buffer<u64>>);
+        if test_config.generic_kernel {
+            kern_builder.arg_named("params", None::<&Buffer<u64>>);
+        }
+        let kern = kern_builder.build()?;
+
+        kern.set_arg("message", Some(&message_buffer))?;
+        kern.set_arg("nonce", Some(&nonce_buffer))?;
+        kern.set_arg("solutions", &solutions_buffer)?;
+        if test_config.generic_kernel {
+            kern.set_arg("params", &params_buffer)?;
+        }
+
+        unsafe { kern.cmd().global_work_size(1).enq()? };
+        solutions_buffer.read(&mut solutions).enq()?;
+
+        // every candidate matches, so work item 0 reports the last one it
+        // tries, for the last init code hash
+        let expected = ((nonce * loop_count + loop_count - 1) as u64) << 32;
+        if solutions[0] != expected {
+            return Err(format!(
+                "Kernel self-test failed: expected a solution for nonce {nonce}, got {:#x}",
+                solutions[0]
+            )
+            .into());
+        }
+        if solutions[4] as usize + 1 != hashes {
+            return Err(format!(
+                "Kernel self-test failed: expected a solution for the last init code hash, \
+                 got index {}",
+                solutions[4]
+            )
+            .into());
+        }
 
-        // get the address that results from the hash
+        let salt = test_config.salt(message.0, solutions[0]);
         let address = solutions[1]
             .to_be_bytes()
             .into_iter()
             .chain(solutions[2].to_be_bytes())
             .chain(solutions[3].to_be_bytes()[..4].to_vec())
             .collect::<Vec<u8>>();
+        let expected = reference::address(&test_config, salt, solutions[4] as usize);
+
+        if address[..] != expected[..] {
+            return Err(format!(
+                "Kernel self-test failed: the GPU computes 0x{} for salt 0x{}, but the CPU \
+                 reference computes 0x{}",
+                hex::encode(&address),
+                hex::encode(salt),
+                hex::encode(expected)
+            )
+            .into());
+        }
+    }
 
-        // count total and leading zero bytes
-        let mut total = 0;
-        let mut leading = 0;
-        for (i, &b) in address.iter().enumerate() {
-            #[rustfmt::skip]
-            if b != 0 { continue; };
+    Ok(())
+}
 
-            if leading == i {
-                leading = i + 1;
-            }
+/// Device-specific specialisations of the kernel, matching the values of
+/// `PLATFORM` in the kernel source.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelPlatform {
+    /// Portable code only.
+    Unknown = 0,
+    /// Rotates through the `rotate` built-in, which NVIDIA compiles to funnel
+    /// shifts.
+    Nvidia = 1,
+    /// Rotates through `amd_bitalign` from the `cl_amd_media_ops` extension.
+    Amd = 2,
+}
 
-            total += 1;
-        }
+impl KernelPlatform {
+    /// Picks the specialisation based on the vendor and extensions of a device.
+    pub fn detect(device: Device) -> ocl::Result<Self> {
+        let vendor = device.vendor()?;
+        let extensions = device.info(DeviceInfo::Extensions)?.to_string();
 
-        let output = format!("0x{} => 0x{}", hex::encode(salt), hex::encode(address),);
+        Ok(Self::for_device(&vendor, &extensions))
+    }
 
-        let show = format!("{output} ({leading} / {total})");
-        match config.reward {
-            RewardVariant::Matching { pattern: _ } => {
-                found_list.push(output.to_string());
-            }
-            _ => {
-                found_list.push(show);
-            }
+    /// Picks the specialisation for a device with the given vendor and
+    /// space-separated list of extensions.
+    pub fn for_device(vendor: &str, extensions: &str) -> Self {
+        let vendor = vendor.to_lowercase();
+
+        if (vendor.contains("advanced micro devices") || vendor.contains("amd"))
+            && extensions
+                .split_whitespace()
+                .any(|ext| ext == "cl_amd_media_ops")
+        {
+            KernelPlatform::Amd
+        } else if vendor.contains("nvidia") {
+            KernelPlatform::Nvidia
+        } else {
+            KernelPlatform::Unknown
         }
+    }
 
-        file.lock_exclusive().expect("Couldn't lock file.");
-
-        writeln!(&file, "{output}").expect("Couldn't write to `output.txt` file.");
+    pub fn name(self) -> &'static str {
+        match self {
+            KernelPlatform::Unknown => "generic",
+            KernelPlatform::Nvidia => "NVIDIA",
+            KernelPlatform::Amd => "AMD",
+        }
+    }
 
-        file.unlock().expect("Couldn't unlock file.");
-        found += 1;
+    /// The options to build the kernel with.
+    pub fn build_options(self) -> String {
+        format!("-D PLATFORM={}", self as u8)
     }
 }
 
-#[track_caller]
-fn output_file(config: &Config) -> File {
-    OpenOptions::new()
-        .append(true)
-        .create(true)
-        .read(true)
-        .open(config.output)
-        .unwrap_or_else(|_| panic!("Could not create or open {} file.", config.output))
+/// The address words the kernel computes first for the criterion, which are
+/// all it needs to rule out most candidates. The rest of the address is only
+/// computed for candidates meeting the condition on these.
+pub fn early_words(reward: &RewardVariant) -> Vec<usize> {
+    let leading_words = |zeros: u8| (0..(zeros as usize).div_ceil(4)).collect();
+    match reward {
+        RewardVariant::LeadingZeros { zeros_threshold } => leading_words(*zeros_threshold),
+        RewardVariant::LeadingAndTotalZeros {
+            leading_zeros_threshold,
+            ..
+        } => leading_words(*leading_zeros_threshold),
+        RewardVariant::TotalZeros { .. } | RewardVariant::LeadingOrTotalZeros { .. } => {
+            (0..5).collect()
+        }
+        // words without any fixed characters are masked out entirely
+        RewardVariant::Matching { pattern } => {
+            let masks = pattern_masks(pattern);
+            (0..5).filter(|&i| masks[i].0 != 0).collect()
+        }
+    }
 }
 
+#[track_caller]
 /// Creates the OpenCL kernel source code by populating the template with the
 /// values from the Config object.
 pub fn mk_kernel_src(config: &Config) -> String {
+    specialised_src(config, &config.reward)
+}
+
+/// The kernel from [`mk_kernel_src`] with the thresholds of its criterion
+/// lowered until every address meets them, for the self-test. Everything else,
+/// down to which address words are computed first, is left as it is.
+fn mk_self_test_kernel_src(config: &Config) -> String {
+    specialised_src(config, &config.reward.always_met())
+}
+
+/// The kernel specialised for the config, checking `reward` rather than the
+/// criterion of the config.
+fn specialised_src(config: &Config, reward: &RewardVariant) -> String {
     let mut src = String::with_capacity(2048 + KERNEL_SRC.len());
 
-    let (caller, chain_id) = match config.salt_variant {
-        SaltVariant::CrosschainSender {
-            chain_id,
-            calling_address,
-        } => {
-            writeln!(src, "#define GENERATE_SEED() SENDER_XCHAIN()").unwrap();
-            (calling_address, Some(chain_id))
-        }
-        SaltVariant::Crosschain { chain_id } => {
-            writeln!(src, "#define GENERATE_SEED() XCHAIN()").unwrap();
-            ([0u8; 20], Some(chain_id))
-        }
-        SaltVariant::Sender { calling_address } => {
-            writeln!(src, "#define GENERATE_SEED() SENDER()").unwrap();
-            (calling_address, None)
-        }
-        SaltVariant::Random => {
-            writeln!(src, "#define GENERATE_SEED() RANDOM()").unwrap();
-            ([0u8; 20], None)
-        }
-    };
+    writeln!(src, "#define LOOP_COUNT {}u", config.loop_count).unwrap();
 
-    match &config.reward {
+    let (seed, message_offset) = seed_template(&config.salt_variant);
+    let mut seed = keccak::lanes(&seed);
+    keccak::absorb(&mut seed, message_offset, 4, "m");
+    keccak::absorb(&mut seed, message_offset + 4, 7, "n");
+    src.push_str(&keccak::first_round("GENERATE_SEED", &seed));
+
+    // the (necessary but not sufficient) condition on the early words that is
+    // checked before computing the rest of the address
+    match reward {
         RewardVariant::LeadingZeros { zeros_threshold } => {
-            writeln!(src, "#define PATTERN() 0").unwrap();
             writeln!(src, "#define LEADING_ZEROES {zeros_threshold}").unwrap();
+            writeln!(src, "#define EARLY_CONDITION() hasLeading(digest)").unwrap();
             writeln!(src, "#define SUCCESS_CONDITION() hasLeading(digest)").unwrap();
         }
         RewardVariant::TotalZeros { zeros_threshold } => {
-            writeln!(src, "#define PATTERN() 0").unwrap();
             writeln!(src, "#define LEADING_ZEROES 0").unwrap();
             writeln!(src, "#define TOTAL_ZEROES {zeros_threshold}").unwrap();
+            writeln!(src, "#define EARLY_CONDITION() 1").unwrap();
             writeln!(src, "#define SUCCESS_CONDITION() hasTotal(digest)").unwrap();
         }
         RewardVariant::LeadingAndTotalZeros {
             leading_zeros_threshold,
             total_zeros_threshold,
         } => {
-            writeln!(src, "#define PATTERN() 0").unwrap();
             writeln!(src, "#define LEADING_ZEROES {leading_zeros_threshold}").unwrap();
             writeln!(src, "#define TOTAL_ZEROES {total_zeros_threshold}").unwrap();
+            writeln!(src, "#define EARLY_CONDITION() hasLeading(digest)").unwrap();
             writeln!(
                 src,
                 "#define SUCCESS_CONDITION() hasLeading(digest) && hasTotal(digest)"
@@ -590,9 +1621,9 @@ pub fn mk_kernel_src(config: &Config) -> String {
             leading_zeros_threshold,
             total_zeros_threshold,
         } => {
-            writeln!(src, "#define PATTERN() 0").unwrap();
             writeln!(src, "#define LEADING_ZEROES {leading_zeros_threshold}").unwrap();
             writeln!(src, "#define TOTAL_ZEROES {total_zeros_threshold}").unwrap();
+            writeln!(src, "#define EARLY_CONDITION() 1").unwrap();
             writeln!(
                 src,
                 "#define SUCCESS_CONDITION() hasLeading(digest) || hasTotal(digest)"
@@ -600,42 +1631,217 @@ pub fn mk_kernel_src(config: &Config) -> String {
             .unwrap();
         }
         RewardVariant::Matching { pattern } => {
+            let masks = pattern_masks(pattern);
             writeln!(src, "#define LEADING_ZEROES 0").unwrap();
-            writeln!(src, "#define PATTERN() \"{pattern}\"").unwrap();
+            for (i, (mask, value)) in masks.iter().enumerate() {
+                writeln!(src, "#define PATTERN_MASK_{i} 0x{mask:08x}u").unwrap();
+                writeln!(src, "#define PATTERN_VALUE_{i} 0x{value:08x}u").unwrap();
+            }
+            writeln!(src, "#define EARLY_CONDITION() isMatching(digest)").unwrap();
             writeln!(src, "#define SUCCESS_CONDITION() isMatching(digest)").unwrap();
         }
-    };
+    }
+    src.push_str(&keccak::final_round(&early_words(&config.reward)));
 
-    let init_code_hash = match config.create_variant {
-        CreateXVariant::Create2 { init_code_hash } => {
-            writeln!(src, "#define CREATE3()").unwrap();
-            init_code_hash
+    match config.create_variant {
+        CreateXVariant::Create2 { .. } => {
+            writeln!(src, "#define CREATE3(a)").unwrap();
         }
         CreateXVariant::Create3 => {
-            writeln!(src, "#define CREATE3() RUN_CREATE3()").unwrap();
-            PROXY_CHILD_CODEHASH
+            // 0xd6 ++ 0x94 ++ proxy address ++ 0x01, where the proxy address is
+            // the last 20 bytes of the CREATE2 hash
+            let mut create3 = [0u8; 200];
+            create3[0] = 0xd6;
+            create3[1] = 0x94;
+            create3[22] = 0x01;
+            create3[23] = 0x01;
+            create3[135] = 0x80;
+
+            let mut create3 = keccak::lanes(&create3);
+            keccak::absorb(&mut create3, 2, 4, "(a[1] >> 32)");
+            keccak::absorb(&mut create3, 6, 8, "a[2]");
+            keccak::absorb(&mut create3, 14, 8, "a[3]");
+            src.push_str(&keccak::first_round("CREATE3_ROUND", &create3));
+            writeln!(src, "#define CREATE3(a) keccakf_tail(a); CREATE3_ROUND(a)").unwrap();
         }
     };
 
-    let caller = caller.iter();
-    let chain_id = chain_id
-        .iter()
-        .flatten()
-        .enumerate()
-        .map(|(i, x)| (i + 20, x));
-    caller.enumerate().chain(chain_id).for_each(|(i, x)| {
-        writeln!(src, "#define S1_{} {}u", i + 12, x).unwrap();
-    });
+    // the guarded salt is the first 32 bytes of the previous hash, saved in `s`
+    // so that it can be reused for each init code hash
+    let init_code_hashes = config.init_code_hashes();
+    writeln!(src, "#define INIT_CODE_HASHES {}u", init_code_hashes.len()).unwrap();
+    for (k, init_code_hash) in init_code_hashes.iter().enumerate() {
+        let create2 = create2_template(&config.factory_address, init_code_hash);
+        let mut create2 = keccak::lanes(&create2);
+        for i in 0..4 {
+            keccak::absorb(&mut create2, 21 + 8 * i, 8, &format!("s[{i}]"));
+        }
+        src.push_str(&keccak::first_round(
+            &format!("CREATE2_ROUND_{k}"),
+            &create2,
+        ));
+    }
+
+    // `k` is the same for all work items, so this does not diverge
+    writeln!(src, "#define CREATE2_ROUND(k, a) switch (k) {{ \\").unwrap();
+    for k in 0..init_code_hashes.len() {
+        writeln!(src, "  case {k}: CREATE2_ROUND_{k}(a) break; \\").unwrap();
+    }
+    writeln!(src, "}}").unwrap();
+
+    src.push_str(KERNEL_SRC);
+
+    src
+}
 
-    let factory = config.factory_address.iter();
-    let hash = init_code_hash.iter();
-    let hash = hash.enumerate().map(|(i, x)| (i + 52, x));
+/// Creates the source of the kernel that [`gpu`] builds for the config.
+pub fn kernel_src(config: &Config) -> String {
+    match config.generic_kernel {
+        true => mk_generic_kernel_src(),
+        false => mk_kernel_src(config),
+    }
+}
 
-    for (i, x) in factory.enumerate().chain(hash) {
-        writeln!(src, "#define S2_{} {}u", i + 1, x).unwrap();
+/// Creates the source of a kernel that reads its configuration from a
+/// parameter buffer (see [`Config::params`]) rather than having it built in.
+/// It is slower than the kernel from [`mk_kernel_src`], but only needs to be
+/// compiled once for any number of configurations.
+pub fn mk_generic_kernel_src() -> String {
+    let mut src = String::with_capacity(1024 + KERNEL_SRC.len());
+
+    writeln!(src, "#define RUNTIME_PARAMS").unwrap();
+    for (name, value) in [
+        ("PARAM_LOOP_COUNT", PARAM_LOOP_COUNT),
+        ("PARAM_INIT_CODE_HASHES", PARAM_INIT_CODE_HASHES),
+        ("PARAM_LEADING_ZEROES", PARAM_LEADING_ZEROES),
+        ("PARAM_TOTAL_ZEROES", PARAM_TOTAL_ZEROES),
+        ("PARAM_CRITERION", PARAM_CRITERION),
+        ("PARAM_CREATE3", PARAM_CREATE3),
+        ("PARAM_MESSAGE_OFFSET", PARAM_MESSAGE_OFFSET),
+        ("PARAM_PATTERN", PARAM_PATTERN),
+        ("PARAM_SEED", PARAM_SEED),
+        ("PARAM_CREATE2", PARAM_CREATE2),
+    ] {
+        writeln!(src, "#define {name} {value}").unwrap();
+    }
+    for (name, value) in [
+        ("CRITERION_LEADING", CRITERION_LEADING),
+        ("CRITERION_TOTAL", CRITERION_TOTAL),
+        ("CRITERION_LEADING_AND_TOTAL", CRITERION_LEADING_AND_TOTAL),
+        ("CRITERION_LEADING_OR_TOTAL", CRITERION_LEADING_OR_TOTAL),
+        ("CRITERION_MATCHING", CRITERION_MATCHING),
+    ] {
+        writeln!(src, "#define {name} {value}UL").unwrap();
     }
 
     src.push_str(KERNEL_SRC);
 
     src
 }
+
+/// Follows each line of an OpenCL build log that refers to a line of the
+/// source (as in `<source>:12:34: error: ...`) with the source lines around it.
+pub fn annotate_build_log(src: &str, log: &str) -> String {
+    let lines: Vec<&str> = src.lines().collect();
+    let mut annotated = String::new();
+
+    for entry in log.lines() {
+        writeln!(annotated, "{entry}").unwrap();
+
+        // the first two consecutive numeric fields are the line and the column
+        let fields: Vec<&str> = entry.split(':').collect();
+        let location = fields.windows(2).find_map(|pair| {
+            let line = pair[0].trim().parse::<usize>().ok()?;
+            let column = pair[1].trim().parse::<usize>().ok()?;
+            Some((line, column))
+        });
+        let Some((line, column)) = location else {
+            continue;
+        };
+        if line == 0 || line > lines.len() {
+            continue;
+        }
+
+        for n in line.saturating_sub(2).max(1)..=line {
+            writeln!(annotated, "  {n:>5} | {}", lines[n - 1]).unwrap();
+        }
+        writeln!(
+            annotated,
+            "        | {}^",
+            " ".repeat(column.saturating_sub(1))
+        )
+        .unwrap();
+    }
+
+    annotated
+}
+
+/// Lays out the preimage of the guarded salt, returning it along with the
+/// offset of the message. Everything except the four message bytes and the
+/// seven nonce bytes that follow them is known ahead of time.
+fn seed_template(salt_variant: &SaltVariant) -> ([u8; 200], usize) {
+    let mut seed = [0u8; 200];
+    let (message_offset, seed_len) = match *salt_variant {
+        SaltVariant::CrosschainSender {
+            chain_id,
+            calling_address,
+        } => {
+            seed[12..32].copy_from_slice(&calling_address);
+            seed[32..64].copy_from_slice(&chain_id);
+            seed[64..84].copy_from_slice(&calling_address);
+            seed[84] = 1;
+            (85, 96)
+        }
+        SaltVariant::Crosschain { chain_id } => {
+            seed[..32].copy_from_slice(&chain_id);
+            seed[52] = 1;
+            (53, 64)
+        }
+        SaltVariant::Sender { calling_address } => {
+            seed[12..32].copy_from_slice(&calling_address);
+            seed[32..52].copy_from_slice(&calling_address);
+            (53, 64)
+        }
+        SaltVariant::Random => (0, 32),
+    };
+    seed[seed_len] = 0x01;
+    seed[135] = 0x80;
+
+    (seed, message_offset)
+}
+
+/// Lays out the preimage of the CREATE2 hash, 0xff ++ factory ++ guarded salt
+/// ++ init code hash, leaving the guarded salt (at byte 21) empty.
+fn create2_template(factory_address: &[u8; 20], init_code_hash: &[u8; 32]) -> [u8; 200] {
+    let mut create2 = [0u8; 200];
+    create2[0] = 0xff;
+    create2[1..21].copy_from_slice(factory_address);
+    create2[53..85].copy_from_slice(init_code_hash);
+    create2[85] = 0x01;
+    create2[135] = 0x80;
+    create2
+}
+
+/// Writes a sponge template into `params` as 25 little-endian lanes.
+fn write_lanes(params: &mut [u64], sponge: &[u8; 200]) {
+    for (param, lane) in params.iter_mut().zip(sponge.chunks_exact(8)) {
+        *param = LittleEndian::read_u64(lane);
+    }
+}
+
+/// Compiles a matching pattern into a mask and value for each of the five
+/// little-endian 32-bit words of the address, so that the kernel can compare
+/// whole words instead of individual characters.
+fn pattern_masks(pattern: &str) -> [(u32, u32); 5] {
+    let mut masks = [(0u32, 0u32); 5];
+    for (i, c) in pattern.chars().enumerate() {
+        if let Some(nibble) = c.to_digit(16) {
+            // the first character of each byte is its high nibble
+            let shift = 8 * (i / 2 % 4) + 4 * (1 - i % 2);
+            let (mask, value) = &mut masks[i / 8];
+            *mask |= 0xf << shift;
+            *value |= nibble << shift;
+        }
+    }
+    masks
+}
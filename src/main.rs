@@ -1,114 +1,526 @@
+use alloy_primitives::hex;
 use clap::Parser;
+use console::style;
 use createxcrunch::{
-    cli::{Cli, Commands},
-    gpu, Config, RewardVariant,
+    benchmark,
+    calldata::Deployment,
+    cli::{
+        Cli, Commands, EstimateArgs, EstimateCommands, ExportCommands, FoundryArgs, ResultsArgs,
+        RunArgs, ServeArgs,
+    },
+    estimate::{self as eta, Estimate},
+    export::{self, InitCall, InitCode},
+    gpu,
+    jobs::{self, Backend, JobFile},
+    kernel_src,
+    output::{self, Solution},
+    progress::Progress,
+    results::{self, SortKey},
+    serve::Service,
+    sink::Hook,
+    Config, RewardVariant,
+};
+#[cfg(feature = "sqlite")]
+use createxcrunch::{
+    cli::{QueryArgs, ResultsCommands},
+    output::OutputFormat,
+    store::{Filter, Store},
+};
+use fs4::FileExt;
+use std::{
+    cmp::Reverse,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
 };
 
 fn main() {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Create2(args) => {
-            let gpu_device_id = args.cli_args.gpu_device_id;
-            let factory = args.cli_args.factory;
-            let caller = args.cli_args.caller;
-            let chain_id = args.cli_args.chain_id;
-            let init_code_hash = args.init_code_hash;
-            let reward = match (
-                args.cli_args.zeros,
-                args.cli_args.total,
-                args.cli_args.either,
-                args.cli_args.pattern,
-            ) {
-                (Some(zeros), None, false, None) => RewardVariant::LeadingZeros {
-                    zeros_threshold: zeros,
-                },
-                (None, Some(total), false, None) => RewardVariant::TotalZeros {
-                    zeros_threshold: total,
-                },
-                (Some(zeros), Some(total), false, None) => RewardVariant::LeadingAndTotalZeros {
-                    leading_zeros_threshold: zeros,
-                    total_zeros_threshold: total,
-                },
-                (Some(zeros), Some(total), true, None) => RewardVariant::LeadingOrTotalZeros {
-                    leading_zeros_threshold: zeros,
-                    total_zeros_threshold: total,
-                },
-                (None, None, false, Some(pattern)) => {
-                    let pattern = pattern
-                        .strip_prefix("0x")
-                        .unwrap_or(&pattern)
-                        .to_owned()
-                        .into_boxed_str();
-                    RewardVariant::Matching { pattern: pattern }
+    let (args, init_code_hashes, estimate_args) = match cli.command {
+        Commands::Create2(args) => (args.cli_args, Some(args.init_code_hashes), None),
+        Commands::Create3(args) => (args, None, None),
+        Commands::Estimate(EstimateArgs {
+            rate,
+            benchmark,
+            search,
+        }) => {
+            let benchmark = Some((rate, Duration::from_secs(benchmark)));
+            match search {
+                EstimateCommands::Create2(args) => {
+                    (args.cli_args, Some(args.init_code_hashes), benchmark)
                 }
-                _ => unreachable!(),
+                EstimateCommands::Create3(args) => (args, None, benchmark),
+            }
+        }
+        Commands::Export(ExportCommands::Foundry(args)) => {
+            return export_foundry(args).unwrap_or_else(|e| panic!("{}", e));
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::Results(ResultsArgs {
+            command: Some(ResultsCommands::Query(args)),
+            ..
+        }) => {
+            return query(args).unwrap_or_else(|e| panic!("{}", e));
+        }
+        Commands::Results(args) => {
+            return results(args).unwrap_or_else(|e| panic!("{}", e));
+        }
+        Commands::Run(args) => {
+            return run(args).unwrap_or_else(|e| panic!("{}", e));
+        }
+        Commands::Serve(args) => {
+            return serve(args).unwrap_or_else(|e| panic!("{}", e));
+        }
+    };
+
+    let reward =
+        RewardVariant::from_args(args.zeros, args.total, args.either, args.pattern.as_deref())
+            .unwrap_or_else(|e| panic!("{}", e));
+    let init_code_hashes = init_code_hashes
+        .as_ref()
+        .map(|hashes| hashes.iter().map(String::as_str).collect::<Vec<_>>());
+
+    match Config::new(
+        args.gpu_device_id,
+        &args.factory,
+        args.caller.as_deref(),
+        args.chain_id,
+        init_code_hashes.as_deref(),
+        reward,
+        &args.output,
+    ) {
+        Ok(mut config) => {
+            config.loop_count = args.loop_count;
+            config.generic_kernel = args.generic_kernel;
+            config.self_test = !args.skip_self_test;
+            config.halt_on_mismatch = !args.continue_on_mismatch;
+            config.format = args.format;
+            config.progress = match args.quiet {
+                true => Progress::Plain,
+                false => args.progress,
             };
-            let output = args.cli_args.output;
-
-            match Config::new(
-                gpu_device_id,
-                &factory,
-                caller.as_deref(),
-                chain_id,
-                Some(&init_code_hash),
-                reward,
-                &output,
-            ) {
-                Ok(config) => match gpu(config) {
-                    Ok(_) => (),
-                    Err(e) => panic!("{}", e),
-                },
-                Err(e) => panic!("{}", e),
+            config.status_interval = Duration::from_secs(args.status_interval);
+            config.metrics_addr = args.metrics_addr;
+            if args.no_kernel_cache {
+                config.kernel_cache = None;
+            } else if let Some(kernel_cache) = args.kernel_cache {
+                config.kernel_cache = Some(kernel_cache.into());
+            }
+
+            let init_codes = match args.clone {
+                Some(_) => vec![None],
+                None => args
+                    .init_code
+                    .iter()
+                    .map(|code| Some(code.as_str()))
+                    .collect(),
             };
-        }
-        Commands::Create3(args) => {
-            let gpu_device_id = args.gpu_device_id;
-            let factory = args.factory;
-            let caller = args.caller;
-            let chain_id = args.chain_id;
-            let reward = match (args.zeros, args.total, args.either, args.pattern) {
-                (Some(zeros), None, false, None) => RewardVariant::LeadingZeros {
-                    zeros_threshold: zeros,
-                },
-                (None, Some(total), false, None) => RewardVariant::TotalZeros {
-                    zeros_threshold: total,
-                },
-                (Some(zeros), Some(total), false, None) => RewardVariant::LeadingAndTotalZeros {
-                    leading_zeros_threshold: zeros,
-                    total_zeros_threshold: total,
-                },
-                (Some(zeros), Some(total), true, None) => RewardVariant::LeadingOrTotalZeros {
-                    leading_zeros_threshold: zeros,
-                    total_zeros_threshold: total,
-                },
-                (None, None, false, Some(pattern)) => {
-                    let pattern = pattern
-                        .strip_prefix("0x")
-                        .unwrap_or(&pattern)
-                        .to_owned()
-                        .into_boxed_str();
-                    RewardVariant::Matching { pattern }
+            let deployments = init_codes
+                .into_iter()
+                .map(|init_code| {
+                    Deployment::from_args(
+                        init_code,
+                        args.clone.as_deref(),
+                        args.init_call.as_deref(),
+                        args.constructor_amount.as_deref(),
+                        args.init_call_amount.as_deref(),
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|e| panic!("{}", e));
+            if let Err(e) = config.set_deployments(deployments) {
+                panic!("{}", e);
+            }
+
+            config.on_found = args.on_found.map(|command| Hook {
+                command,
+                timeout: Duration::from_secs(args.on_found_timeout),
+            });
+            #[cfg(feature = "sqlite")]
+            if let Some(database) = args.database {
+                config.database = Some(database.into());
+            }
+
+            if let Some((rate, benchmark)) = estimate_args {
+                return estimate(&config, rate, benchmark).unwrap_or_else(|e| panic!("{}", e));
+            }
+
+            if let Some(path) = args.emit_kernel {
+                std::fs::write(&path, kernel_src(&config))
+                    .unwrap_or_else(|e| panic!("Could not write kernel to {}: {}", path, e));
+                println!("Wrote the generated kernel to {}.", path);
+            }
+
+            if args.dry_run {
+                println!("{}", config.summary());
+                return;
+            }
+
+            // stop after the current dispatch on the first Ctrl-C or SIGTERM,
+            // and right away on the second
+            let stop = Arc::new(AtomicBool::new(false));
+            let signalled = stop.clone();
+            ctrlc::set_handler(move || {
+                if signalled.swap(true, Ordering::SeqCst) {
+                    std::process::exit(130);
                 }
-                _ => unreachable!(),
-            };
-            let output = args.output;
-
-            match Config::new(
-                gpu_device_id,
-                &factory,
-                caller.as_deref(),
-                chain_id,
-                None,
-                reward,
-                &output,
-            ) {
-                Ok(config) => match gpu(config) {
-                    Ok(_) => (),
-                    Err(e) => panic!("{}", e),
-                },
+            })
+            .unwrap_or_else(|e| panic!("Could not set up the signal handler: {}", e));
+            config.stop = Some(stop);
+
+            match gpu(config) {
+                Ok(_) => (),
                 Err(e) => panic!("{}", e),
+            }
+        }
+        Err(e) => panic!("{}", e),
+    };
+}
+
+fn estimate(config: &Config, rate: Option<f64>, duration: Duration) -> Result<(), String> {
+    let rate = match rate {
+        Some(rate) => rate,
+        None => {
+            println!(
+                "Benchmarking the kernel on device {} for {} seconds...",
+                config.gpu_device,
+                duration.as_secs()
+            );
+            benchmark(config, duration).map_err(|e| e.to_string())?
+        }
+    };
+    let estimate = Estimate::new(config.probability(&config.reward), rate);
+
+    println!("criterion:          {}", config.reward.description());
+    println!(
+        "probability:        1 in {:.4e} per salt ({:.2} bits)",
+        estimate.expected_candidates(),
+        estimate.expected_candidates().log2()
+    );
+    println!("rate:               {:.2} million salts per second", rate);
+    println!(
+        "expected time:      {}",
+        eta::human(estimate.expected_time())
+    );
+    for q in [0.5, 0.9, 0.99] {
+        println!(
+            "{}% chance within:  {}",
+            q * 100.0,
+            eta::human(estimate.percentile(q))
+        );
+    }
+
+    if !estimate.feasible() {
+        println!(
+            "{}",
+            style(format!(
+                "Warning: this search is infeasible at this rate, as a salt is expected only \
+                 after {}. Lower the thresholds, or mine for several init code hashes at once.",
+                eta::human(estimate.expected_time())
+            ))
+            .yellow()
+            .bold()
+        );
+    }
+
+    Ok(())
+}
+
+fn run(args: RunArgs) -> Result<(), String> {
+    let file = JobFile::read(Path::new(&args.jobfile))?;
+    let backend = match args.cpu {
+        true => Backend::Cpu,
+        false => Backend::Gpu(args.gpu_device_id),
+    };
+    let progress = match args.quiet {
+        true => Progress::Plain,
+        false => args.progress,
+    };
+
+    // stop the current job on the first Ctrl-C or SIGTERM, and right away on
+    // the second
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let signalled = interrupt.clone();
+    ctrlc::set_handler(move || {
+        if signalled.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    })
+    .map_err(|e| format!("could not set up the signal handler: {}", e))?;
+
+    let summaries = jobs::run(&file, backend, progress, &interrupt)?;
+
+    if !matches!(progress, Progress::Json | Progress::None) {
+        println!();
+        for (index, summary) in summaries.iter().enumerate() {
+            let state = match file.jobs[index].done(summary) {
+                true => "done",
+                false => "stopped",
             };
+            println!(
+                "{}: {}, {} salt(s) found in {} ({:.2} M/s)",
+                file.label(index),
+                state,
+                summary.found(),
+                eta::human(Some(summary.runtime)),
+                summary.rate()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn serve(args: ServeArgs) -> Result<(), String> {
+    let backends = match args.cpu {
+        true => vec![Backend::Cpu],
+        false => args.devices.into_iter().map(Backend::Gpu).collect(),
+    };
+    let service = Service::start(
+        args.listen,
+        &backends,
+        args.concurrent,
+        args.output.map(PathBuf::from),
+    )
+    .map_err(|e| format!("could not listen on {}: {}", args.listen, e))?;
+    println!(
+        "Serving jobs on http://{} with {} worker(s). Press Ctrl-C to stop.",
+        args.listen,
+        backends.len()
+    );
+
+    let (signal, signalled) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = signal.send(());
+    })
+    .map_err(|e| format!("could not set up the signal handler: {}", e))?;
+    let _ = signalled.recv();
+
+    println!("Cancelling the running jobs...");
+    service.shutdown();
+
+    Ok(())
+}
+
+fn export_foundry(args: FoundryArgs) -> Result<(), String> {
+    let solutions = output::read_solutions(Path::new(&args.records))?;
+    let solution = export::select_solution(solutions, args.salt.as_deref())?;
+
+    let init_code = InitCode::from_args(
+        args.artifact.as_deref(),
+        args.constructor_args.as_deref(),
+        args.init_code.as_deref(),
+    )?;
+    let init_call = args
+        .init_call
+        .as_deref()
+        .map(|data| {
+            InitCall::from_args(
+                data,
+                args.constructor_amount.as_deref(),
+                args.init_call_amount.as_deref(),
+            )
+        })
+        .transpose()?;
+
+    let script = export::foundry_script(
+        &solution,
+        &args.contract_name,
+        &init_code,
+        init_call.as_ref(),
+    )?;
+    std::fs::write(&args.output, script)
+        .map_err(|e| format!("could not write {}: {}", args.output, e))?;
+    println!(
+        "Wrote a script deploying {} to {}.",
+        solution.checksum_address, args.output
+    );
+
+    Ok(())
+}
+
+fn results(args: ResultsArgs) -> Result<(), String> {
+    let reward =
+        RewardVariant::from_args(args.zeros, args.total, args.either, args.pattern.as_deref())?;
+    let pattern = match (&reward, args.sort) {
+        (RewardVariant::Matching { pattern }, _) => Some(pattern.to_string()),
+        (_, SortKey::Pattern) => return Err("ranking by pattern requires --matching".to_string()),
+        _ => None,
+    };
+    let init_code_hashes = args
+        .init_code_hashes
+        .as_ref()
+        .map(|hashes| hashes.iter().map(String::as_str).collect::<Vec<_>>());
+    let config = Config::new(
+        0,
+        &args.factory,
+        args.caller.as_deref(),
+        args.chain_id,
+        init_code_hashes.as_deref(),
+        reward,
+        "",
+    )?;
+
+    let mut entries = vec![];
+    let mut unreadable = 0;
+    for file in &args.files {
+        let (file_entries, failures) = results::read_entries(Path::new(file))?;
+        entries.extend(file_entries);
+        unreadable += failures.len();
+        for failure in failures {
+            println!("{}", style(format!("UNREADABLE {}", failure)).red());
         }
     }
+    let read = entries.len();
+    let entries = results::dedupe(entries);
+    let duplicates = read - entries.len();
+
+    let mut verified = vec![];
+    let mut failed = 0;
+    for entry in entries {
+        match entry.verify(&config) {
+            Ok(index) => verified.push((entry, index)),
+            Err(e) => {
+                failed += 1;
+                println!(
+                    "{}",
+                    style(format!(
+                        "FAILED {}: 0x{} => 0x{}: {}",
+                        entry.source,
+                        hex::encode(entry.salt),
+                        hex::encode(entry.address),
+                        e
+                    ))
+                    .red()
+                );
+            }
+        }
+    }
+
+    verified.sort_by_key(|(entry, _)| Reverse(entry.score(args.sort, pattern.as_deref())));
+    if let Some(top) = args.top {
+        verified.truncate(top);
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.database {
+        let store = Store::open(Path::new(path)).map_err(|e| e.to_string())?;
+        let mut stored = 0;
+        for (entry, index) in &verified {
+            let solution = Solution::new(
+                &config,
+                entry.salt,
+                entry.address,
+                *index,
+                &entry.device,
+                entry.timestamp,
+            );
+            stored += store.insert(&solution).map_err(|e| e.to_string())? as usize;
+        }
+        println!("Stored {} new entries in {}.", stored, path);
+    }
+
+    match args.output {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&path)
+                .map_err(|e| format!("could not open {}: {}", path, e))?;
+            file.lock_exclusive()
+                .map_err(|e| format!("could not lock {}: {}", path, e))?;
+            for (entry, index) in &verified {
+                let solution = Solution::new(
+                    &config,
+                    entry.salt,
+                    entry.address,
+                    *index,
+                    &entry.device,
+                    entry.timestamp,
+                );
+                output::write_solution(
+                    &file,
+                    args.format,
+                    &solution,
+                    entry.init_code_hash.is_some(),
+                )
+                .map_err(|e| format!("could not write to {}: {}", path, e))?;
+            }
+            file.unlock()
+                .map_err(|e| format!("could not unlock {}: {}", path, e))?;
+            println!("Wrote {} entries to {}.", verified.len(), path);
+        }
+        None => {
+            for (rank, (entry, _)) in verified.iter().enumerate() {
+                let score = match &pattern {
+                    Some(pattern) => format!("{} matching", entry.pattern_score(pattern)),
+                    None => format!("{} / {}", entry.leading_zeros(), entry.total_zeros()),
+                };
+                println!(
+                    "{:>4}. 0x{} => 0x{} ({})",
+                    rank + 1,
+                    hex::encode(entry.salt),
+                    hex::encode(entry.address),
+                    score
+                );
+            }
+        }
+    }
+
+    println!(
+        "{} entries read, {} unreadable, {} duplicates, {} failed verification.",
+        read, unreadable, duplicates, failed
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn query(args: QueryArgs) -> Result<(), String> {
+    let store = Store::open(Path::new(&args.database)).map_err(|e| e.to_string())?;
+    let solutions = store
+        .query(&Filter {
+            create_variant: args.create_variant,
+            factory: args.factory,
+            caller: args.caller,
+            chain_id: args.chain_id,
+            init_code_hash: args.init_code_hash,
+            min_leading_zeros: args.zeros,
+            min_total_zeros: args.total,
+            limit: args.top,
+        })
+        .map_err(|e| e.to_string())?;
+
+    match args.format {
+        OutputFormat::Text => {
+            for solution in &solutions {
+                println!(
+                    "{} ({} / {}, {})",
+                    solution.text(true),
+                    solution.leading_zeros,
+                    solution.total_zeros,
+                    solution.create_variant
+                );
+            }
+        }
+        OutputFormat::Jsonl => {
+            for solution in &solutions {
+                println!("{}", serde_json::to_string(solution).unwrap());
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for solution in &solutions {
+                writer.serialize(solution).map_err(|e| e.to_string())?;
+            }
+            writer.flush().map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,218 @@
+//! Prometheus metrics of a run, served over HTTP.
+
+use crate::{output::Solution, Config, CreateXVariant, SaltVariant};
+use alloy_primitives::Address;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tiny_http::{Header, Response, Server};
+
+#[derive(Default)]
+struct State {
+    hashes: u64,
+    /// Candidates per second during the last dispatch.
+    rate: f64,
+    dispatches: u64,
+    dispatch_seconds: f64,
+    last_dispatch_seconds: f64,
+    /// Solutions found so far, by criterion.
+    solutions: BTreeMap<String, u64>,
+}
+
+/// The metrics of a run, served in the Prometheus text format on `/metrics`
+/// until dropped.
+pub struct Metrics {
+    state: Arc<Mutex<State>>,
+    /// Labels of the device and the job, set on every metric.
+    labels: String,
+    started: f64,
+    server: Arc<Server>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Metrics {
+    /// Starts serving the metrics of mining with the config on `device`.
+    pub fn serve(addr: SocketAddr, config: &Config, device: &str) -> io::Result<Self> {
+        let server = Arc::new(Server::http(addr).map_err(io::Error::other)?);
+
+        let variant = match config.create_variant {
+            CreateXVariant::Create2 { .. } => "create2",
+            CreateXVariant::Create3 => "create3",
+        };
+        let caller = match config.salt_variant {
+            SaltVariant::CrosschainSender {
+                calling_address, ..
+            }
+            | SaltVariant::Sender { calling_address } => {
+                Address::from(calling_address).to_checksum(None)
+            }
+            _ => String::new(),
+        };
+        let labels = format!(
+            "device=\"{}\",variant=\"{}\",factory=\"{}\",caller=\"{}\"",
+            escape(device),
+            variant,
+            Address::from(config.factory_address).to_checksum(None),
+            caller
+        );
+
+        let mut metrics = Self {
+            state: Arc::default(),
+            labels,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs_f64(),
+            server: server.clone(),
+            worker: None,
+        };
+
+        let state = metrics.state.clone();
+        let (labels, started) = (metrics.labels.clone(), metrics.started);
+        metrics.worker = Some(thread::spawn(move || {
+            // ends once the server is unblocked
+            for request in server.incoming_requests() {
+                let response = match request.url() {
+                    "/metrics" => {
+                        let body = render(&state.lock().unwrap(), &labels, started);
+                        Response::from_string(body).with_header(
+                            Header::from_bytes(
+                                "Content-Type",
+                                "text/plain; version=0.0.4; charset=utf-8",
+                            )
+                            .unwrap(),
+                        )
+                    }
+                    _ => Response::from_string("not found\n").with_status_code(404),
+                };
+                // the scraper may have gone away
+                let _ = request.respond(response);
+            }
+        }));
+
+        Ok(metrics)
+    }
+
+    /// The address the metrics are served on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Records a kernel dispatch that tried `candidates` in `duration`.
+    pub fn dispatch(&self, candidates: u64, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.hashes += candidates;
+        state.dispatches += 1;
+        state.dispatch_seconds += duration.as_secs_f64();
+        state.last_dispatch_seconds = duration.as_secs_f64();
+        if !duration.is_zero() {
+            state.rate = candidates as f64 / duration.as_secs_f64();
+        }
+    }
+
+    /// Records a found solution.
+    pub fn solution(&self, solution: &Solution) {
+        let mut state = self.state.lock().unwrap();
+        *state
+            .solutions
+            .entry(solution.criterion.clone())
+            .or_default() += 1;
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        render(&self.state.lock().unwrap(), &self.labels, self.started)
+    }
+}
+
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn render(state: &State, labels: &str, started: f64) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+        for (suffix_and_labels, value) in samples {
+            writeln!(out, "{}{} {}", name, suffix_and_labels, value).unwrap();
+        }
+    };
+    let plain = |value: String| vec![(format!("{{{}}}", labels), value)];
+
+    metric(
+        "createxcrunch_start_time_seconds",
+        "gauge",
+        "Time the run started, in seconds since the Unix epoch.",
+        &plain(started.to_string()),
+    );
+    metric(
+        "createxcrunch_hashes_total",
+        "counter",
+        "Candidate salts tried.",
+        &plain(state.hashes.to_string()),
+    );
+    metric(
+        "createxcrunch_hash_rate",
+        "gauge",
+        "Candidate salts tried per second during the last kernel dispatch.",
+        &plain(state.rate.to_string()),
+    );
+    metric(
+        "createxcrunch_dispatch_duration_seconds",
+        "summary",
+        "Time taken by kernel dispatches.",
+        &[
+            (
+                format!("_sum{{{}}}", labels),
+                state.dispatch_seconds.to_string(),
+            ),
+            (
+                format!("_count{{{}}}", labels),
+                state.dispatches.to_string(),
+            ),
+        ],
+    );
+    metric(
+        "createxcrunch_last_dispatch_duration_seconds",
+        "gauge",
+        "Time taken by the last kernel dispatch.",
+        &plain(state.last_dispatch_seconds.to_string()),
+    );
+    metric(
+        "createxcrunch_solutions_total",
+        "counter",
+        "Salts found, by search criterion.",
+        &state
+            .solutions
+            .iter()
+            .map(|(criterion, found)| {
+                (
+                    format!("{{{},criterion=\"{}\"}}", labels, escape(criterion)),
+                    found.to_string(),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    out
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,187 @@
+//! Records of found salts and the formats they are written in.
+
+use crate::{reference, Config, CreateXVariant, SaltVariant};
+use alloy_primitives::{hex, Address};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::Write, path::Path};
+
+/// Format of the records written to the output file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// `0x<salt> => 0x<address>` lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma-separated values, with a header row.
+    Csv,
+}
+
+/// A found salt, along with everything needed to make sense of it later.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Solution {
+    pub salt: String,
+    pub guarded_salt: String,
+    pub address: String,
+    pub checksum_address: String,
+    pub leading_zeros: u8,
+    pub total_zeros: u8,
+    pub criterion: String,
+    pub create_variant: String,
+    pub factory: String,
+    pub caller: Option<String>,
+    pub chain_id: Option<u64>,
+    pub init_code_hash: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub device: String,
+    /// Base-2 logarithm of the expected number of candidates per solution.
+    pub difficulty_bits: f64,
+    /// ABI-encoded CreateX call deploying the contract with the salt.
+    pub calldata: Option<String>,
+    /// `cast send` command line making the same call.
+    pub cast_send: Option<String>,
+}
+
+impl Solution {
+    /// Describes a salt found for the config, where `index` selects the init
+    /// code hash for CREATE2 deployments.
+    pub fn new(
+        config: &Config,
+        salt: [u8; 32],
+        address: [u8; 20],
+        index: usize,
+        device: &str,
+        timestamp: u64,
+    ) -> Self {
+        let leading_zeros = address.iter().take_while(|&&b| b == 0).count() as u8;
+        let total_zeros = address.iter().filter(|&&b| b == 0).count() as u8;
+
+        let (caller, chain_id) = match config.salt_variant {
+            SaltVariant::CrosschainSender {
+                chain_id,
+                calling_address,
+            } => (Some(calling_address), Some(chain_id)),
+            SaltVariant::Crosschain { chain_id } => (None, Some(chain_id)),
+            SaltVariant::Sender { calling_address } => (Some(calling_address), None),
+            SaltVariant::Random => (None, None),
+        };
+
+        let (create_variant, init_code_hash) = match config.create_variant {
+            CreateXVariant::Create2 {
+                ref init_code_hashes,
+            } => ("create2", Some(init_code_hashes[index])),
+            CreateXVariant::Create3 => ("create3", None),
+        };
+        let deployment = config.deployment(index);
+
+        Self {
+            salt: hex::encode_prefixed(salt),
+            guarded_salt: hex::encode_prefixed(reference::guarded_salt(&config.salt_variant, salt)),
+            address: hex::encode_prefixed(address),
+            checksum_address: Address::from(address).to_checksum(None),
+            leading_zeros,
+            total_zeros,
+            criterion: config.reward.description(),
+            create_variant: create_variant.to_string(),
+            factory: Address::from(config.factory_address).to_checksum(None),
+            caller: caller.map(|caller| Address::from(caller).to_checksum(None)),
+            chain_id: chain_id
+                .map(|chain_id| u64::from_be_bytes(chain_id[24..].try_into().unwrap())),
+            init_code_hash: init_code_hash.map(hex::encode_prefixed),
+            timestamp,
+            device: device.to_string(),
+            difficulty_bits: config.reward.difficulty_bits(),
+            calldata: deployment.map(|deployment| {
+                hex::encode_prefixed(deployment.calldata(&config.create_variant, salt))
+            }),
+            cast_send: deployment.map(|deployment| {
+                deployment.cast_send(config.factory_address, &config.create_variant, salt)
+            }),
+        }
+    }
+
+    /// The plain text form, noting the init code hash when mining for several.
+    pub fn text(&self, with_init_code_hash: bool) -> String {
+        match (&self.init_code_hash, with_init_code_hash) {
+            (Some(init_code_hash), true) => {
+                format!("{} => {} => {}", self.salt, self.address, init_code_hash)
+            }
+            _ => format!("{} => {}", self.salt, self.address),
+        }
+    }
+}
+
+/// Appends a solution to the output file, starting CSV files with a header.
+/// The caller is expected to hold a lock on the file.
+pub fn write_solution(
+    file: &File,
+    format: OutputFormat,
+    solution: &Solution,
+    with_init_code_hash: bool,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            writeln!(&*file, "{}", solution.text(with_init_code_hash))?;
+            // indented, so that the salt lines can still be told apart
+            if let (Some(calldata), Some(cast_send)) = (&solution.calldata, &solution.cast_send) {
+                writeln!(&*file, "    calldata: {calldata}")?;
+                writeln!(&*file, "    {cast_send}")?;
+            }
+            Ok(())
+        }
+        OutputFormat::Jsonl => {
+            let line = serde_json::to_string(solution)?;
+            writeln!(&*file, "{line}")
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(file.metadata()?.len() == 0)
+                .from_writer(file);
+            writer.serialize(solution)?;
+            writer.flush()
+        }
+    }
+}
+
+/// Tells the format of an output file from its contents.
+pub fn detect_format(contents: &str) -> OutputFormat {
+    match contents.trim_start() {
+        contents if contents.starts_with('{') => OutputFormat::Jsonl,
+        contents if contents.starts_with("salt,") => OutputFormat::Csv,
+        _ => OutputFormat::Text,
+    }
+}
+
+/// Reads the solutions back from a jsonl or csv output file. Text files only
+/// hold the salt and address, which is not enough to rebuild a solution.
+pub fn read_solutions(path: &Path) -> Result<Vec<Solution>, String> {
+    read_records(path)?
+        .into_iter()
+        .map(|record| record.map_err(|e| format!("invalid record in {}: {}", path.display(), e)))
+        .collect()
+}
+
+/// Reads the records of a jsonl or csv output file, each of which is parsed on
+/// its own, so that a bad record does not hide the rest.
+pub fn read_records(path: &Path) -> Result<Vec<Result<Solution, String>>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    match detect_format(&contents) {
+        OutputFormat::Jsonl => Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+            .collect()),
+        OutputFormat::Csv => Ok(csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .map(|record| record.map_err(|e| e.to_string()))
+            .collect()),
+        OutputFormat::Text => Err(format!(
+            "{} holds plain text records, which lack the deployment settings; mine with --format jsonl or csv",
+            path.display()
+        )),
+    }
+}
@@ -0,0 +1,378 @@
+//! Reporting the progress of a run, either on a redrawn screen or as one line
+//! per record for logs.
+
+use crate::{
+    dashboard::Dashboard,
+    estimate::{human, Estimate},
+    output::Solution,
+};
+use console::{style, Term};
+use separator::Separatable;
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    io,
+    time::{Duration, Instant},
+};
+use terminal_size::{terminal_size, Height};
+
+/// Number of notices shown on the screen.
+const MAX_NOTICES: usize = 3;
+
+/// How progress is reported.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum Progress {
+    /// The dashboard on a terminal, plain lines otherwise.
+    #[default]
+    Auto,
+    /// A full-screen dashboard with keyboard controls.
+    Tui,
+    /// A status screen redrawn every second.
+    Screen,
+    /// Single-line status records and a line per solution.
+    Plain,
+    /// Status records and solutions as JSON objects, one per line.
+    Json,
+    /// Nothing, for runs driven by other code, such as the jobs of the
+    /// service.
+    None,
+}
+
+/// What a run has done, reported when it ends.
+#[derive(Clone, Debug, Default)]
+pub struct RunSummary {
+    pub runtime: Duration,
+    /// Candidates tried.
+    pub hashes: u64,
+    /// Solutions found, by leading and total zero bytes.
+    pub by_score: BTreeMap<(u8, u8), u64>,
+    /// The solution with the most leading, then total zero bytes.
+    pub best: Option<Solution>,
+}
+
+impl RunSummary {
+    /// Counts a found solution.
+    pub fn add(&mut self, solution: &Solution) {
+        let score = (solution.leading_zeros, solution.total_zeros);
+        *self.by_score.entry(score).or_default() += 1;
+        if self
+            .best
+            .as_ref()
+            .is_none_or(|best| score > (best.leading_zeros, best.total_zeros))
+        {
+            self.best = Some(solution.clone());
+        }
+    }
+
+    /// Adds the runtime, candidates and solutions of another run.
+    pub fn merge(&mut self, other: &RunSummary) {
+        self.runtime += other.runtime;
+        self.hashes += other.hashes;
+        for (score, found) in &other.by_score {
+            *self.by_score.entry(*score).or_default() += found;
+        }
+        if let Some(best) = &other.best {
+            let score = (best.leading_zeros, best.total_zeros);
+            if self
+                .best
+                .as_ref()
+                .is_none_or(|ours| score > (ours.leading_zeros, ours.total_zeros))
+            {
+                self.best = Some(best.clone());
+            }
+        }
+    }
+
+    pub fn found(&self) -> u64 {
+        self.by_score.values().sum()
+    }
+
+    /// Millions of candidates tried per second.
+    pub fn rate(&self) -> f64 {
+        match self.runtime.is_zero() {
+            true => 0.0,
+            false => self.hashes as f64 / self.runtime.as_secs_f64() / 1e6,
+        }
+    }
+}
+
+/// A snapshot of a run.
+#[derive(Clone)]
+pub(crate) struct Status {
+    pub device: String,
+    pub runtime: Duration,
+    /// Number of kernel dispatches so far.
+    pub cycles: u64,
+    /// Candidates tried per dispatch.
+    pub work_size: u64,
+    /// Millions of candidates tried per second.
+    pub rate: f64,
+    /// Millions of candidates tried so far.
+    pub attempts: f64,
+    /// Chance of a candidate meeting the criterion.
+    pub probability: f64,
+    pub found: u64,
+    pub search_space: String,
+    pub variant: String,
+    pub threshold: String,
+}
+
+/// What was asked for on the dashboard.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Controls {
+    pub paused: bool,
+    /// Number of zero bytes by which the thresholds are raised.
+    pub raise: u8,
+    pub quit: bool,
+}
+
+pub(crate) struct Reporter {
+    progress: Progress,
+    interval: Duration,
+    last_status: Option<Instant>,
+    term: Term,
+    dashboard: Option<Dashboard>,
+    /// Notices kept for the screen, which redraws everything at each status.
+    notices: Vec<String>,
+}
+
+impl Reporter {
+    /// Reports in the given way, printing status records every `interval`
+    /// unless the screen is used.
+    pub fn new(progress: Progress, interval: Duration) -> io::Result<Self> {
+        let term = Term::stdout();
+        let progress = match progress {
+            Progress::Auto if term.is_term() => Progress::Tui,
+            Progress::Auto => Progress::Plain,
+            progress => progress,
+        };
+        let interval = match progress {
+            Progress::Screen | Progress::Tui => Duration::from_secs(1),
+            _ => interval,
+        };
+        let dashboard = match progress {
+            Progress::Tui => Some(Dashboard::open()?),
+            _ => None,
+        };
+
+        Ok(Self {
+            progress,
+            interval,
+            last_status: None,
+            term,
+            dashboard,
+            notices: vec![],
+        })
+    }
+
+    /// Prints a note about the run.
+    pub fn message(&mut self, message: &str) {
+        match (self.progress, &mut self.dashboard) {
+            (_, Some(dashboard)) => dashboard.log(message),
+            (Progress::Json, _) => {
+                println!("{}", json!({ "event": "message", "message": message }))
+            }
+            (Progress::None, _) => {}
+            _ => println!("{}", message),
+        }
+    }
+
+    /// Handles the keys pressed on the dashboard since the last call, and
+    /// returns what has been asked for so far.
+    pub fn poll(&mut self) -> io::Result<Controls> {
+        match &mut self.dashboard {
+            Some(dashboard) => dashboard.poll(),
+            None => Ok(Controls::default()),
+        }
+    }
+
+    /// Whether it is time for the next status report.
+    pub fn status_due(&self) -> bool {
+        self.last_status
+            .is_none_or(|last| last.elapsed() >= self.interval)
+    }
+
+    /// Reports the status of the run, along with the most recent of the
+    /// solutions found so far on the screen.
+    pub fn status(&mut self, status: &Status, found_list: &[String]) -> io::Result<()> {
+        self.last_status = Some(Instant::now());
+
+        let runtime = hms(status.runtime);
+        let estimate = Estimate::new(status.probability, status.rate);
+        let eta = match status.rate > 0.0 {
+            true => human(estimate.expected_time()),
+            false => "unknown".to_string(),
+        };
+
+        match self.progress {
+            Progress::None => {}
+            Progress::Tui | Progress::Auto => {
+                if let Some(dashboard) = &mut self.dashboard {
+                    dashboard.status(status.clone())?;
+                }
+            }
+            Progress::Screen => {
+                self.term.clear_screen()?;
+
+                // calculate the terminal height, defaulting to a height of ten rows
+                let height = terminal_size().map(|(_w, Height(h))| h).unwrap_or(10);
+
+                // display information about the total runtime and work size
+                self.term.write_line(&format!(
+                    "total runtime: {} ({} cycles)\t\t\t\
+                     work size per cycle: {}",
+                    runtime,
+                    status.cycles,
+                    status.work_size.separated_string(),
+                ))?;
+
+                // display information about the attempt rate and found solutions
+                self.term.write_line(&format!(
+                    "rate: {:.2} million attempts per second\t\t\t\
+                     total found this run: {}\t\t\t\
+                     expected time per hit: {}",
+                    status.rate, status.found, eta
+                ))?;
+
+                // display information about the current search criteria
+                self.term.write_line(&format!(
+                    "current search space: {}\t\t\
+                     threshold: mining for {} address {}",
+                    status.search_space, status.variant, status.threshold
+                ))?;
+
+                // display the most recent notices, in red
+                let notices = &self.notices[self.notices.len().saturating_sub(MAX_NOTICES)..];
+                for notice in notices {
+                    self.term
+                        .write_line(&style(notice).red().bold().to_string())?;
+                }
+
+                // display recently found solutions based on terminal height
+                let rows = (height as usize).saturating_sub(4 + notices.len()).max(1);
+                let recently_found = &found_list[found_list.len().saturating_sub(rows)..];
+                self.term.write_line(&recently_found.join("\n"))?;
+            }
+            Progress::Plain => println!(
+                "status: runtime {}, {} cycles, {:.2} million attempts per second, {} found, \
+                 expected time per hit {}, search space {}",
+                runtime, status.cycles, status.rate, status.found, eta, status.search_space
+            ),
+            Progress::Json => println!(
+                "{}",
+                json!({
+                    "event": "status",
+                    "runtime_secs": status.runtime.as_secs(),
+                    "cycles": status.cycles,
+                    "work_size": status.work_size,
+                    "attempts_per_second": status.rate * 1e6,
+                    "found": status.found,
+                    "expected_secs_per_hit": estimate
+                        .expected_time()
+                        .filter(|_| status.rate > 0.0)
+                        .map(|time| time.as_secs_f64()),
+                    "search_space": status.search_space,
+                })
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Reports something that went wrong while mining. The screen shows the
+    /// most recent notices above the solutions at each status.
+    pub fn notice(&mut self, notice: &str) {
+        match self.progress {
+            Progress::Auto | Progress::None => {}
+            Progress::Screen => self.notices.push(notice.to_string()),
+            Progress::Tui => {
+                if let Some(dashboard) = &mut self.dashboard {
+                    dashboard.log(notice);
+                }
+            }
+            Progress::Plain => println!("{}", notice),
+            Progress::Json => println!("{}", json!({ "event": "notice", "message": notice })),
+        }
+    }
+
+    /// Reports a solution, which the screen shows with the next status.
+    pub fn solution(&mut self, solution: &Solution, line: &str) {
+        match self.progress {
+            Progress::Screen | Progress::Auto | Progress::None => {}
+            Progress::Tui => {
+                if let Some(dashboard) = &mut self.dashboard {
+                    dashboard.solution(solution.clone(), line.to_string());
+                }
+            }
+            Progress::Plain => println!("found: {}", line),
+            Progress::Json => {
+                let mut record = serde_json::to_value(solution).unwrap();
+                record["event"] = json!("solution");
+                println!("{}", record);
+            }
+        }
+    }
+
+    /// Closes the dashboard, if shown, and reports the summary of the run.
+    pub fn summary(mut self, summary: &RunSummary) {
+        drop(self.dashboard.take());
+
+        if self.progress == Progress::None {
+            return;
+        }
+        if self.progress == Progress::Json {
+            let by_score: Vec<_> = summary
+                .by_score
+                .iter()
+                .rev()
+                .map(|(&(leading_zeros, total_zeros), &found)| {
+                    json!({
+                        "leading_zeros": leading_zeros,
+                        "total_zeros": total_zeros,
+                        "found": found,
+                    })
+                })
+                .collect();
+            println!(
+                "{}",
+                json!({
+                    "event": "summary",
+                    "runtime_secs": summary.runtime.as_secs_f64(),
+                    "hashes": summary.hashes,
+                    "attempts_per_second": summary.rate() * 1e6,
+                    "found": summary.found(),
+                    "by_score": by_score,
+                    "best": summary.best,
+                })
+            );
+            return;
+        }
+
+        println!("run summary:");
+        println!("  runtime:      {}", hms(summary.runtime));
+        println!("  salts tried:  {}", summary.hashes.separated_string());
+        println!(
+            "  average rate: {:.2} million salts per second",
+            summary.rate()
+        );
+        println!("  found:        {}", summary.found());
+        for (&(leading_zeros, total_zeros), found) in summary.by_score.iter().rev() {
+            println!(
+                "    {} leading / {} total zero bytes: {}",
+                leading_zeros, total_zeros, found
+            );
+        }
+        if let Some(best) = &summary.best {
+            println!(
+                "  best address: {} ({} / {}) with salt {}",
+                best.checksum_address, best.leading_zeros, best.total_zeros, best.salt
+            );
+        }
+    }
+}
+
+/// Formats a duration as hours, minutes and seconds.
+pub(crate) fn hms(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+}
@@ -0,0 +1,80 @@
+//! CPU reference implementation of the address derivation done by the kernel,
+//! used to double-check what the device reports.
+
+use crate::{Config, CreateXVariant, RewardVariant, SaltVariant, PROXY_CHILD_CODEHASH};
+use alloy_primitives::keccak256;
+use itertools::chain;
+
+/// Computes the guarded salt the way CreateX's `_guard` does.
+pub fn guarded_salt(salt_variant: &SaltVariant, salt: [u8; 32]) -> [u8; 32] {
+    let preimage: Vec<u8> = match *salt_variant {
+        SaltVariant::CrosschainSender {
+            chain_id,
+            calling_address,
+        } => chain!([0u8; 12], calling_address, chain_id, salt).collect(),
+        SaltVariant::Crosschain { chain_id } => chain!(chain_id, salt).collect(),
+        SaltVariant::Sender { calling_address } => {
+            chain!([0u8; 12], calling_address, salt).collect()
+        }
+        SaltVariant::Random => salt.to_vec(),
+    };
+
+    keccak256(preimage).0
+}
+
+/// Computes the address CreateX deploys to for a salt, using the init code hash
+/// at `index` for CREATE2 deployments.
+pub fn address(config: &Config, salt: [u8; 32], index: usize) -> [u8; 20] {
+    let guarded_salt = guarded_salt(&config.salt_variant, salt);
+
+    let init_code_hash = match config.create_variant {
+        CreateXVariant::Create2 {
+            ref init_code_hashes,
+        } => init_code_hashes[index],
+        CreateXVariant::Create3 => PROXY_CHILD_CODEHASH,
+    };
+    let create2 = create2_address(config.factory_address, guarded_salt, init_code_hash);
+
+    match config.create_variant {
+        CreateXVariant::Create2 { .. } => create2,
+        CreateXVariant::Create3 => {
+            // the proxy deploys the contract with CREATE, at nonce 1
+            let preimage: Vec<u8> = chain!([0xd6, 0x94], create2, [0x01]).collect();
+            keccak256(preimage)[12..].try_into().unwrap()
+        }
+    }
+}
+
+fn create2_address(factory: [u8; 20], salt: [u8; 32], init_code_hash: [u8; 32]) -> [u8; 20] {
+    let preimage: Vec<u8> = chain!([0xff], factory, salt, init_code_hash).collect();
+    keccak256(preimage)[12..].try_into().unwrap()
+}
+
+/// Checks whether an address meets the search criterion.
+pub fn meets_criterion(reward: &RewardVariant, address: &[u8; 20]) -> bool {
+    let leading = address.iter().take_while(|&&b| b == 0).count() as u8;
+    let total = address.iter().filter(|&&b| b == 0).count() as u8;
+
+    match reward {
+        RewardVariant::LeadingZeros { zeros_threshold } => leading >= *zeros_threshold,
+        RewardVariant::TotalZeros { zeros_threshold } => total >= *zeros_threshold,
+        RewardVariant::LeadingAndTotalZeros {
+            leading_zeros_threshold,
+            total_zeros_threshold,
+        } => leading >= *leading_zeros_threshold && total >= *total_zeros_threshold,
+        RewardVariant::LeadingOrTotalZeros {
+            leading_zeros_threshold,
+            total_zeros_threshold,
+        } => leading >= *leading_zeros_threshold || total >= *total_zeros_threshold,
+        RewardVariant::Matching { pattern } => hex_chars(address)
+            .zip(pattern.chars())
+            .all(|(c, p)| p == 'X' || c == p),
+    }
+}
+
+fn hex_chars(address: &[u8; 20]) -> impl Iterator<Item = char> + '_ {
+    address
+        .iter()
+        .flat_map(|b| [b >> 4, b & 0xf])
+        .map(|nibble| char::from_digit(nibble as u32, 16).unwrap())
+}
@@ -0,0 +1,182 @@
+//! Reading back, auditing and ranking the output files of past runs.
+
+use crate::{
+    output::{self, OutputFormat},
+    reference, Config, CreateXVariant,
+};
+use alloy_primitives::hex;
+use std::path::Path;
+
+/// Score by which entries are ranked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum SortKey {
+    /// Leading zero bytes, then total zero bytes.
+    #[default]
+    Leading,
+    /// Total zero bytes, then leading zero bytes.
+    Total,
+    /// Number of characters matching the pattern.
+    Pattern,
+}
+
+/// A salt and address read from an output file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub salt: [u8; 32],
+    pub address: [u8; 20],
+    pub init_code_hash: Option<[u8; 32]>,
+    pub device: String,
+    /// Seconds since the Unix epoch, or zero if unknown.
+    pub timestamp: u64,
+    /// File and line or record the entry was read from.
+    pub source: String,
+}
+
+impl Entry {
+    pub fn leading_zeros(&self) -> u8 {
+        self.address.iter().take_while(|&&b| b == 0).count() as u8
+    }
+
+    pub fn total_zeros(&self) -> u8 {
+        self.address.iter().filter(|&&b| b == 0).count() as u8
+    }
+
+    /// Number of characters of the address matching those of the pattern
+    /// that are not `X`.
+    pub fn pattern_score(&self, pattern: &str) -> usize {
+        hex::encode(self.address)
+            .chars()
+            .zip(pattern.chars())
+            .filter(|&(c, p)| p != 'X' && c == p)
+            .count()
+    }
+
+    /// The score by `key`, higher being better. Without a pattern, nothing
+    /// matches it.
+    pub fn score(&self, key: SortKey, pattern: Option<&str>) -> (usize, usize) {
+        let (leading, total) = (self.leading_zeros() as usize, self.total_zeros() as usize);
+        match key {
+            SortKey::Leading => (leading, total),
+            SortKey::Total => (total, leading),
+            SortKey::Pattern => (self.pattern_score(pattern.unwrap_or_default()), 0),
+        }
+    }
+
+    /// Checks the entry against the job on the CPU, returning the index of
+    /// the init code hash it was found for.
+    pub fn verify(&self, config: &Config) -> Result<usize, String> {
+        let indices = match (&config.create_variant, self.init_code_hash) {
+            (CreateXVariant::Create2 { init_code_hashes }, Some(init_code_hash)) => {
+                match init_code_hashes.iter().position(|&h| h == init_code_hash) {
+                    Some(index) => vec![index],
+                    None => return Err("init code hash is not part of the job".to_string()),
+                }
+            }
+            (CreateXVariant::Create2 { init_code_hashes }, None) => {
+                (0..init_code_hashes.len()).collect()
+            }
+            (CreateXVariant::Create3, _) => vec![0],
+        };
+
+        let index = indices
+            .into_iter()
+            .find(|&index| reference::address(config, self.salt, index) == self.address)
+            .ok_or_else(|| "salt does not derive the address".to_string())?;
+
+        if !reference::meets_criterion(&config.reward, &self.address) {
+            return Err(format!(
+                "address does not meet the criterion {}",
+                config.reward.description()
+            ));
+        }
+
+        Ok(index)
+    }
+}
+
+/// Reads the entries of an output file in any of the output formats. A line or
+/// record that cannot be read does not stop the rest from being read; it is
+/// returned as a failure naming where it is instead.
+pub fn read_entries(path: &Path) -> Result<(Vec<Entry>, Vec<String>), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    if output::detect_format(&contents) != OutputFormat::Text {
+        return Ok(split(
+            output::read_records(path)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, record)| {
+                    let source = format!("{} record {}", path.display(), i + 1);
+                    let solution = record.map_err(|e| format!("{}: {}", source, e))?;
+                    Ok(Entry {
+                        salt: decode(&solution.salt, &source)?,
+                        address: decode(&solution.address, &source)?,
+                        init_code_hash: solution
+                            .init_code_hash
+                            .map(|init_code_hash| decode(&init_code_hash, &source))
+                            .transpose()?,
+                        device: solution.device,
+                        timestamp: solution.timestamp,
+                        source,
+                    })
+                }),
+        ));
+    }
+
+    Ok(split(
+        contents
+            .lines()
+            .enumerate()
+            // the calldata written below a salt is indented
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with(char::is_whitespace))
+            .map(|(i, line)| {
+                let source = format!("{}:{}", path.display(), i + 1);
+                let fields: Vec<&str> = line.split("=>").map(str::trim).collect();
+                let (salt, address, init_code_hash) = match fields[..] {
+                    [salt, address] => (salt, address, None),
+                    [salt, address, init_code_hash] => (salt, address, Some(init_code_hash)),
+                    _ => return Err(format!("{}: unrecognised line", source)),
+                };
+
+                Ok(Entry {
+                    salt: decode(salt, &source)?,
+                    address: decode(address, &source)?,
+                    init_code_hash: init_code_hash
+                        .map(|init_code_hash| decode(init_code_hash, &source))
+                        .transpose()?,
+                    device: "unknown".to_string(),
+                    timestamp: 0,
+                    source,
+                })
+            }),
+    ))
+}
+
+/// Drops repeated entries, keeping the first of each. Entries of the same salt
+/// and address are the same, whether or not they note the init code hash.
+pub fn dedupe(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut seen = std::collections::HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| seen.insert((entry.salt, entry.address)))
+        .collect()
+}
+
+fn decode<const N: usize>(s: &str, source: &str) -> Result<[u8; N], String> {
+    hex::decode(s)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("{}: invalid {}-byte hex value {}", source, N, s))
+}
+
+fn split(results: impl Iterator<Item = Result<Entry, String>>) -> (Vec<Entry>, Vec<String>) {
+    let (mut entries, mut failures) = (vec![], vec![]);
+    for result in results {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => failures.push(e),
+        }
+    }
+    (entries, failures)
+}
@@ -0,0 +1,294 @@
+//! Sharing one device between several jobs.
+//!
+//! The jobs take turns by kernel dispatch, each getting a share of the device
+//! in proportion to its weight: the next dispatch always goes to the job that
+//! has tried the fewest candidates per unit of weight. A newly admitted job
+//! starts level with the others, rather than catching up on what it missed.
+//!
+//! On a GPU, all jobs share one build of the runtime-parameterized kernel (see
+//! [`Config::generic_kernel`]). Switching it from one job to the next only
+//! takes writing the parameters of that job, so admitting a job never waits
+//! for a compile.
+
+use crate::{
+    cpu::{self, CpuMiner},
+    jobs::Backend,
+    output::Solution,
+    progress::{Progress, Reporter, RunSummary},
+    self_test,
+    sink::SolutionSink,
+    Candidate, Config, Dispatcher, KernelPlatform,
+};
+use ocl::{Context, Device, Platform};
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A job mined alongside others on a device.
+pub struct Job<'a> {
+    pub id: u64,
+    /// Mining stops once its stop flag is set.
+    pub config: Config<'a>,
+    pub sinks: Vec<Box<dyn SolutionSink>>,
+    /// Share of the device relative to the other jobs.
+    pub weight: u32,
+}
+
+/// Supplies the jobs a device is shared between, and takes their results.
+pub trait JobSource<'a> {
+    /// The next job to mine alongside the `active` ones, if any. With none
+    /// active, this may block until a job comes, and `None` stops the
+    /// scheduler.
+    fn next(&mut self, active: usize) -> Option<Job<'a>>;
+
+    /// Takes what a job has done once it has stopped, or why it failed.
+    fn finished(&mut self, id: u64, result: Result<RunSummary, String>);
+}
+
+enum Miner {
+    Gpu(Box<Dispatcher>),
+    Cpu(CpuMiner),
+}
+
+/// The device the jobs are mined on.
+struct Target {
+    /// The OpenCL context, the device and its build options, on a GPU.
+    gpu: Option<(Context, Device, String)>,
+    name: String,
+    /// The miner the jobs take turns on, once the first one is admitted.
+    miner: Option<Miner>,
+    /// Whether the kernel has been checked against the CPU reference.
+    tested: bool,
+    /// The job the miner is set up for.
+    current: Option<u64>,
+}
+
+impl Target {
+    fn new(backend: Backend) -> ocl::Result<Self> {
+        let gpu = match backend {
+            Backend::Gpu(index) => {
+                let platform = Platform::new(ocl::core::default_platform()?);
+                let device = Device::by_idx_wrap(platform, index as usize)?;
+                let context = Context::builder()
+                    .platform(platform)
+                    .devices(device)
+                    .build()?;
+                let build_options = KernelPlatform::detect(device)?.build_options();
+                Some((context, device, build_options))
+            }
+            Backend::Cpu => None,
+        };
+        let name = match &gpu {
+            Some((_, device, _)) => device.name()?,
+            None => cpu::DEVICE.to_string(),
+        };
+
+        Ok(Self {
+            gpu,
+            name,
+            miner: None,
+            tested: false,
+            current: None,
+        })
+    }
+
+    /// Readies the miner for a newly admitted job, building the kernel for the
+    /// first one and checking it for the first one asking for a self-test.
+    fn admit(&mut self, config: &Config, reporter: &mut Reporter) -> ocl::Result<()> {
+        let Some((context, device, build_options)) = &self.gpu else {
+            self.miner
+                .get_or_insert_with(|| Miner::Cpu(CpuMiner::new()));
+            return Ok(());
+        };
+        let config = Config {
+            generic_kernel: true,
+            ..config.clone()
+        };
+
+        if self.miner.is_none() {
+            let dispatcher =
+                Dispatcher::new(&config, context, *device, build_options, &mut |note| {
+                    reporter.notice(note)
+                })?;
+            self.miner = Some(Miner::Gpu(Box::new(dispatcher)));
+            self.current = None;
+        }
+        if config.self_test && !self.tested {
+            let Some(Miner::Gpu(dispatcher)) = &self.miner else {
+                unreachable!("the kernel is built above");
+            };
+            self_test(
+                &config,
+                dispatcher,
+                context,
+                *device,
+                build_options,
+                &mut |note| reporter.notice(note),
+            )?;
+            self.tested = true;
+        }
+
+        Ok(())
+    }
+
+    /// Runs one dispatch for the job, switching the miner over to it first if
+    /// the previous dispatch was for another job. Returns the number of
+    /// candidates tried and those meeting the criterion.
+    fn dispatch(&mut self, id: u64, config: &Config) -> ocl::Result<(u64, Vec<Candidate>)> {
+        let switch = self.current != Some(id);
+        self.current = Some(id);
+
+        match self.miner.as_mut().expect("a job has been admitted") {
+            Miner::Gpu(dispatcher) => {
+                if switch {
+                    dispatcher.set_params(config)?;
+                }
+                dispatcher.enqueue()?;
+                let found = dispatcher.collect(config)?;
+                Ok((dispatcher.candidates(), found.into_iter().collect()))
+            }
+            // found by the reference itself
+            Miner::Cpu(miner) => Ok((miner.candidates(), miner.batch(config))),
+        }
+    }
+}
+
+struct Active<'a> {
+    job: Job<'a>,
+    summary: RunSummary,
+    started: Instant,
+    /// Candidates tried per unit of weight.
+    pass: f64,
+    error: Option<String>,
+}
+
+impl Active<'_> {
+    fn stopped(&self) -> bool {
+        self.error.is_some()
+            || self
+                .job
+                .config
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::SeqCst))
+    }
+
+    /// Runs one dispatch of the job on the target, handing the solutions to
+    /// its sinks.
+    fn dispatch(&mut self, target: &mut Target, reporter: &mut Reporter) -> Result<(), String> {
+        let config = &self.job.config;
+        let (candidates, found) = target
+            .dispatch(self.job.id, config)
+            .map_err(|e| e.to_string())?;
+        self.summary.hashes += candidates;
+        self.pass += candidates as f64 / self.job.weight as f64;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for candidate in found {
+            if let Err(mismatch) = candidate.verify(config) {
+                if config.halt_on_mismatch {
+                    return Err(format!("Kernel verification failed: {mismatch}"));
+                }
+                reporter.notice(&format!("MISMATCH: {mismatch}"));
+                continue;
+            }
+            let solution = Solution::new(
+                config,
+                candidate.salt,
+                candidate.address,
+                candidate.index,
+                &target.name,
+                timestamp,
+            );
+            for sink in self.job.sinks.iter_mut() {
+                sink.write(&solution)
+                    .map_err(|e| format!("Could not write solution: {}", e))?;
+            }
+            self.summary.add(&solution);
+        }
+
+        self.summary.runtime = self.started.elapsed();
+        if let Some(run_summary) = &config.run_summary {
+            *run_summary.lock().unwrap() = self.summary.clone();
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<RunSummary, String> {
+        self.summary.runtime = self.started.elapsed();
+        for sink in self.job.sinks.iter_mut() {
+            sink.finish()
+                .map_err(|e| format!("Could not finish writing solutions: {}", e))?;
+        }
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.summary),
+        }
+    }
+}
+
+/// Shares the device of the backend between the jobs of the source, until it
+/// runs out of jobs. Notes such as solutions the CPU reference disagrees with
+/// are reported as asked for by `progress`.
+pub fn share<'a>(
+    backend: Backend,
+    progress: Progress,
+    source: &mut dyn JobSource<'a>,
+) -> ocl::Result<()> {
+    // there is no single status to show for several jobs, so only the notes
+    // are reported, on plain lines unless asked for JSON
+    let progress = match progress {
+        Progress::Json | Progress::None => progress,
+        _ => Progress::Plain,
+    };
+    let mut reporter = Reporter::new(progress, Duration::MAX)?;
+    let mut target = Target::new(backend)?;
+    let mut active: Vec<Active<'a>> = vec![];
+
+    loop {
+        while let Some(job) = source.next(active.len()) {
+            if let Err(e) = target.admit(&job.config, &mut reporter) {
+                source.finished(job.id, Err(e.to_string()));
+                continue;
+            }
+            // start level with the job furthest behind
+            let pass = active
+                .iter()
+                .map(|active| active.pass)
+                .min_by(f64::total_cmp)
+                .unwrap_or(0.0);
+            active.push(Active {
+                job,
+                summary: RunSummary::default(),
+                started: Instant::now(),
+                pass,
+                error: None,
+            });
+        }
+        if active.is_empty() {
+            return Ok(());
+        }
+
+        // the next dispatch goes to the job furthest behind its share
+        let turn = active
+            .iter_mut()
+            .min_by(|a, b| a.pass.total_cmp(&b.pass))
+            .unwrap();
+        if let Err(e) = turn.dispatch(&mut target, &mut reporter) {
+            turn.error = Some(e);
+        }
+
+        // retire the jobs that have stopped or failed
+        let (stopped, running): (Vec<_>, Vec<_>) = active.into_iter().partition(Active::stopped);
+        active = running;
+        for job in stopped {
+            let id = job.job.id;
+            source.finished(id, job.finish());
+        }
+    }
+}
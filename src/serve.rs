@@ -0,0 +1,506 @@
+//! A local HTTP/JSON service mining jobs on the devices of the machine.
+//!
+//! Jobs are queued and mined on the first device with room for them. A device
+//! mines up to a set number of jobs at once, sharing its kernel dispatches
+//! between them in proportion to their weights, see [`crate::scheduler`]:
+//!
+//! - `POST /jobs` submits a [`JobSpec`] and returns the queued job. The output
+//!   of the job is set by the service.
+//! - `GET /jobs` lists all jobs, and `GET /jobs/<id>` returns one.
+//! - `GET /jobs/<id>/solutions` returns the solutions found so far, and with
+//!   `?stream` sends them as JSON lines while they are found, until the job
+//!   ends.
+//! - `DELETE /jobs/<id>` cancels a job.
+
+use crate::{
+    jobs::{self, Backend, JobSpec},
+    output::{OutputFormat, Solution},
+    progress::{Progress, RunSummary},
+    scheduler::{self, JobSource},
+    sink::{FileSink, SolutionSink},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tiny_http::{HTTPVersion, Header, Method, Request, Response, Server};
+
+/// Where a job is in its life.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    /// Found the requested number of solutions.
+    Done,
+    Cancelled,
+    Failed,
+}
+
+impl JobState {
+    /// Whether the job has ended.
+    pub fn finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Done | JobState::Cancelled | JobState::Failed
+        )
+    }
+}
+
+struct Job {
+    spec: JobSpec,
+    state: JobState,
+    device: Option<String>,
+    error: Option<String>,
+    /// Seconds since the Unix epoch.
+    submitted: u64,
+    cancelled: bool,
+    stop: Arc<AtomicBool>,
+    summary: Arc<Mutex<RunSummary>>,
+    solutions: Vec<Solution>,
+}
+
+impl Job {
+    fn view(&self, id: u64) -> Value {
+        let summary = self.summary.lock().unwrap();
+        json!({
+            "id": id,
+            "state": self.state,
+            "device": self.device,
+            "error": self.error,
+            "submitted": self.submitted,
+            "runtime_secs": summary.runtime.as_secs_f64(),
+            "hashes": summary.hashes,
+            "rate": summary.rate(),
+            "found": self.solutions.len(),
+            "spec": self.spec,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: BTreeMap<u64, Job>,
+    queue: VecDeque<u64>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+struct Shared {
+    jobs: Mutex<Jobs>,
+    /// Notified whenever a job is queued, changes state or finds a solution.
+    changed: Condvar,
+    /// File every solution of every job is appended to, as JSON lines.
+    output: Option<PathBuf>,
+}
+
+/// The job service, running until shut down or dropped.
+pub struct Service {
+    shared: Arc<Shared>,
+    server: Arc<Server>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Service {
+    /// Starts serving on `addr`, with a worker for each of the backends mining
+    /// up to `concurrent` jobs at once.
+    pub fn start(
+        addr: SocketAddr,
+        backends: &[Backend],
+        concurrent: usize,
+        output: Option<PathBuf>,
+    ) -> io::Result<Self> {
+        let server = Arc::new(Server::http(addr).map_err(io::Error::other)?);
+        let shared = Arc::new(Shared {
+            jobs: Mutex::default(),
+            changed: Condvar::new(),
+            output,
+        });
+
+        let mut threads = backends
+            .iter()
+            .map(|&backend| {
+                let mut worker = Worker {
+                    shared: shared.clone(),
+                    backend,
+                    concurrent: concurrent.max(1),
+                    watches: BTreeMap::new(),
+                };
+                thread::spawn(move || {
+                    if let Err(e) = scheduler::share(backend, Progress::Plain, &mut worker) {
+                        eprintln!("Could not mine on {}: {}", backend.name(), e);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let (accepting, listener) = (shared.clone(), server.clone());
+        threads.push(thread::spawn(move || {
+            // ends once the server is unblocked
+            for request in listener.incoming_requests() {
+                let shared = accepting.clone();
+                // streams stay open while their job runs
+                thread::spawn(move || handle(&shared, request));
+            }
+        }));
+
+        Ok(Self {
+            shared,
+            server,
+            threads,
+        })
+    }
+
+    /// The address the service listens on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Cancels the running jobs, and waits for the workers to stop.
+    pub fn shutdown(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        {
+            let mut jobs = self.shared.jobs.lock().unwrap();
+            jobs.shutdown = true;
+            for job in jobs.jobs.values_mut() {
+                if !job.state.finished() {
+                    job.cancelled = true;
+                    job.stop.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+        self.shared.changed.notify_all();
+        self.server.unblock();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Hands the queued jobs to the scheduler of a device, up to `concurrent` at
+/// once, and records how they end.
+struct Worker {
+    shared: Arc<Shared>,
+    backend: Backend,
+    concurrent: usize,
+    /// Guards of the time limits of the running jobs.
+    watches: BTreeMap<u64, mpsc::Sender<()>>,
+}
+
+impl JobSource<'static> for Worker {
+    fn next(&mut self, active: usize) -> Option<scheduler::Job<'static>> {
+        let shared = self.shared.clone();
+        let mut jobs = shared.jobs.lock().unwrap();
+        loop {
+            if jobs.shutdown || active >= self.concurrent {
+                return None;
+            }
+            let Some(id) = jobs.queue.pop_front() else {
+                // only wait for jobs while the device is idle
+                if active > 0 {
+                    return None;
+                }
+                jobs = shared.changed.wait(jobs).unwrap();
+                continue;
+            };
+
+            let job = jobs.jobs.get_mut(&id).unwrap();
+            job.state = JobState::Running;
+            job.device = Some(self.backend.name());
+            let prepared = prepare(&shared, self.backend, id, job);
+            drop(jobs);
+            shared.changed.notify_all();
+
+            match prepared {
+                Ok((job, watch)) => {
+                    if let Some(watch) = watch {
+                        self.watches.insert(id, watch);
+                    }
+                    return Some(job);
+                }
+                Err(e) => self.finished(id, Err(e)),
+            }
+            jobs = shared.jobs.lock().unwrap();
+        }
+    }
+
+    fn finished(&mut self, id: u64, result: Result<RunSummary, String>) {
+        self.watches.remove(&id);
+
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        let job = jobs.jobs.get_mut(&id).unwrap();
+        match result {
+            Ok(ran) => {
+                *job.summary.lock().unwrap() = ran;
+                job.state = match job.cancelled {
+                    true => JobState::Cancelled,
+                    false => JobState::Done,
+                };
+            }
+            Err(e) => {
+                job.state = JobState::Failed;
+                job.error = Some(e);
+            }
+        }
+        drop(jobs);
+        self.shared.changed.notify_all();
+    }
+}
+
+/// The job for the scheduler, along with the guard of its time limit.
+fn prepare(
+    shared: &Arc<Shared>,
+    backend: Backend,
+    id: u64,
+    job: &Job,
+) -> Result<(scheduler::Job<'static>, Option<mpsc::Sender<()>>), String> {
+    let mut config = job.spec.config(backend.gpu_device(), "")?;
+    config.stop = Some(job.stop.clone());
+    config.run_summary = Some(job.summary.clone());
+
+    let mut sinks: Vec<Box<dyn SolutionSink>> = vec![];
+    if let Some(path) = &shared.output {
+        let sink = FileSink::open(path, OutputFormat::Jsonl, true)
+            .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        sinks.push(Box::new(sink));
+    }
+    sinks.push(Box::new(JobSink {
+        shared: shared.clone(),
+        id,
+        max_solutions: job.spec.max_solutions,
+        stop: job.stop.clone(),
+    }));
+
+    let watch = job
+        .spec
+        .max_time
+        .map(|max| jobs::stop_when(job.stop.clone(), Some(Duration::from_secs(max)), None));
+    let job = scheduler::Job {
+        id,
+        config,
+        sinks,
+        weight: job.spec.weight.unwrap_or(1),
+    };
+
+    Ok((job, watch))
+}
+
+/// Collects the solutions of a job, stopping it once it has found enough.
+struct JobSink {
+    shared: Arc<Shared>,
+    id: u64,
+    max_solutions: Option<usize>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SolutionSink for JobSink {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        let job = jobs.jobs.get_mut(&self.id).unwrap();
+        // a dispatch may find more than asked for
+        if self
+            .max_solutions
+            .is_none_or(|max| job.solutions.len() < max)
+        {
+            job.solutions.push(solution.clone());
+        }
+        if self
+            .max_solutions
+            .is_some_and(|max| job.solutions.len() >= max)
+        {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+        drop(jobs);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+fn handle(shared: &Arc<Shared>, mut request: Request) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    let (status, body) = match (request.method(), segments.as_slice()) {
+        (Method::Post, ["jobs"]) => {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => submit(shared, &body),
+                Err(e) => error(400, format!("could not read the request: {}", e)),
+            }
+        }
+        (Method::Get, ["jobs"]) => {
+            let jobs = shared.jobs.lock().unwrap();
+            let views = jobs
+                .jobs
+                .iter()
+                .map(|(id, job)| job.view(*id))
+                .collect::<Vec<_>>();
+            (200, Value::from(views))
+        }
+        (method, ["jobs", id, rest @ ..]) => match id.parse::<u64>() {
+            Ok(id) if shared.jobs.lock().unwrap().jobs.contains_key(&id) => match (method, rest) {
+                (Method::Get, []) => {
+                    let jobs = shared.jobs.lock().unwrap();
+                    (200, jobs.jobs[&id].view(id))
+                }
+                (Method::Delete, []) => cancel(shared, id),
+                (Method::Get, ["solutions"]) if query.split('&').any(|param| param == "stream") => {
+                    return stream(shared, id, request);
+                }
+                (Method::Get, ["solutions"]) => {
+                    let jobs = shared.jobs.lock().unwrap();
+                    (200, json!(jobs.jobs[&id].solutions))
+                }
+                _ => error(405, "method not allowed".to_string()),
+            },
+            _ => error(404, format!("no job {}", id)),
+        },
+        _ => error(404, "not found".to_string()),
+    };
+
+    let response = Response::from_string(format!("{}\n", body))
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    // the client may have gone away
+    let _ = request.respond(response);
+}
+
+fn error(status: u16, message: String) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+fn submit(shared: &Shared, body: &str) -> (u16, Value) {
+    let spec: JobSpec = match serde_json::from_str(body) {
+        Ok(spec) => spec,
+        Err(e) => return error(400, format!("invalid job: {}", e)),
+    };
+    if spec.output.is_some() {
+        return error(400, "the output of jobs is set by the service".to_string());
+    }
+    // reject jobs the workers could not run
+    if let Err(e) = spec.config(0, "") {
+        return error(400, format!("invalid job: {}", e));
+    }
+
+    let mut jobs = shared.jobs.lock().unwrap();
+    let id = jobs.next_id;
+    jobs.next_id += 1;
+    let job = Job {
+        spec,
+        state: JobState::Queued,
+        device: None,
+        error: None,
+        submitted: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        cancelled: false,
+        stop: Arc::default(),
+        summary: Arc::default(),
+        solutions: vec![],
+    };
+    let view = job.view(id);
+    jobs.jobs.insert(id, job);
+    jobs.queue.push_back(id);
+    drop(jobs);
+    shared.changed.notify_all();
+
+    (201, view)
+}
+
+fn cancel(shared: &Shared, id: u64) -> (u16, Value) {
+    let mut jobs = shared.jobs.lock().unwrap();
+    jobs.queue.retain(|queued| *queued != id);
+    let job = jobs.jobs.get_mut(&id).unwrap();
+    match job.state {
+        JobState::Queued => job.state = JobState::Cancelled,
+        JobState::Running => {
+            // the worker marks it cancelled once mining has stopped
+            job.cancelled = true;
+            job.stop.store(true, Ordering::SeqCst);
+        }
+        _ => (),
+    }
+    let view = job.view(id);
+    drop(jobs);
+    shared.changed.notify_all();
+
+    (200, view)
+}
+
+/// Sends the solutions of a job as JSON lines as they are found, until the job
+/// ends. The response is chunked unless the client speaks HTTP/1.0, which
+/// reads until the connection closes instead.
+fn stream(shared: &Shared, id: u64, request: Request) {
+    let chunked = *request.http_version() != HTTPVersion(1, 0);
+    let mut writer = request.into_writer();
+    let send = |writer: &mut dyn Write, data: &[u8]| -> io::Result<()> {
+        match chunked {
+            true => write!(writer, "{:x}\r\n", data.len())
+                .and_then(|_| writer.write_all(data))
+                .and_then(|_| writer.write_all(b"\r\n")),
+            false => writer.write_all(data),
+        }?;
+        writer.flush()
+    };
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n{}\r\n",
+        match chunked {
+            true => "Transfer-Encoding: chunked\r\n",
+            false => "Connection: close\r\n",
+        }
+    );
+    if writer.write_all(head.as_bytes()).is_err() {
+        return;
+    }
+
+    let mut sent = 0;
+    loop {
+        let lines = {
+            let mut jobs = shared.jobs.lock().unwrap();
+            loop {
+                let job = &jobs.jobs[&id];
+                if job.solutions.len() > sent {
+                    break job.solutions[sent..]
+                        .iter()
+                        .map(|solution| format!("{}\n", serde_json::to_string(solution).unwrap()))
+                        .collect::<String>();
+                }
+                if job.state.finished() || jobs.shutdown {
+                    let _ = match chunked {
+                        true => writer.write_all(b"0\r\n\r\n"),
+                        false => Ok(()),
+                    }
+                    .and_then(|_| writer.flush());
+                    return;
+                }
+                jobs = shared.changed.wait(jobs).unwrap();
+            }
+        };
+        sent += lines.lines().count();
+        // stop once the client has gone away
+        if send(&mut writer, lines.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
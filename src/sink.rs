@@ -0,0 +1,309 @@
+//! Destinations for found salts.
+//!
+//! The mining loop hands every verified solution to each of its sinks in turn.
+//! Besides the built-in sinks, library users can implement [`SolutionSink`] to
+//! send solutions anywhere else, and pass them to [`crate::gpu_with_sinks`].
+
+use crate::{
+    output::{self, OutputFormat, Solution},
+    Config,
+};
+use fs4::FileExt;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Receives the solutions found during a run.
+pub trait SolutionSink: Send {
+    /// Takes a solution. An error stops the run.
+    fn write(&mut self, solution: &Solution) -> io::Result<()>;
+
+    /// Called once the run ends.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends solutions to a file, holding an exclusive lock while writing so
+/// that several runs can share it.
+pub struct FileSink {
+    file: File,
+    format: OutputFormat,
+    with_init_code_hash: bool,
+}
+
+impl FileSink {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: &Path, format: OutputFormat, with_init_code_hash: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .read(true)
+            .open(path)?;
+
+        Ok(Self {
+            file,
+            format,
+            with_init_code_hash,
+        })
+    }
+
+    /// The output file of the config.
+    pub fn for_config(config: &Config) -> io::Result<Self> {
+        Self::open(
+            Path::new(config.output),
+            config.format,
+            config.multiple_init_code_hashes(),
+        )
+    }
+}
+
+impl SolutionSink for FileSink {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        self.file.lock_exclusive()?;
+        let written =
+            output::write_solution(&self.file, self.format, solution, self.with_init_code_hash);
+        self.file.unlock()?;
+        written
+    }
+
+    /// Makes sure the solutions written are on disk.
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Prints solutions to standard output.
+pub struct StdoutSink {
+    format: OutputFormat,
+    with_init_code_hash: bool,
+    wrote_header: bool,
+}
+
+impl StdoutSink {
+    pub fn new(format: OutputFormat, with_init_code_hash: bool) -> Self {
+        Self {
+            format,
+            with_init_code_hash,
+            wrote_header: false,
+        }
+    }
+}
+
+impl SolutionSink for StdoutSink {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        match self.format {
+            OutputFormat::Text => {
+                writeln!(stdout, "{}", solution.text(self.with_init_code_hash))
+            }
+            OutputFormat::Jsonl => writeln!(stdout, "{}", serde_json::to_string(solution)?),
+            OutputFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(!self.wrote_header)
+                    .from_writer(stdout);
+                writer.serialize(solution)?;
+                self.wrote_header = true;
+                writer.flush()
+            }
+        }
+    }
+}
+
+/// Runs a command for every solution and waits for it to exit. The solution
+/// is passed as a JSON object on the command's standard input, and its main
+/// fields as `CREATEXCRUNCH_*` environment variables. The output of the
+/// command is discarded.
+pub struct CommandSink {
+    program: String,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl CommandSink {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            timeout: None,
+        }
+    }
+
+    /// Runs a command line through the shell.
+    pub fn shell(command: &str) -> Self {
+        if cfg!(windows) {
+            Self::new("cmd", vec!["/C".to_string(), command.to_string()])
+        } else {
+            Self::new("sh", vec!["-c".to_string(), command.to_string()])
+        }
+    }
+
+    /// Kills the command if it runs for longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl SolutionSink for CommandSink {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .envs([
+                ("CREATEXCRUNCH_SALT", solution.salt.clone()),
+                ("CREATEXCRUNCH_ADDRESS", solution.address.clone()),
+                (
+                    "CREATEXCRUNCH_CHECKSUM_ADDRESS",
+                    solution.checksum_address.clone(),
+                ),
+                (
+                    "CREATEXCRUNCH_LEADING_ZEROS",
+                    solution.leading_zeros.to_string(),
+                ),
+                (
+                    "CREATEXCRUNCH_TOTAL_ZEROS",
+                    solution.total_zeros.to_string(),
+                ),
+                ("CREATEXCRUNCH_CRITERION", solution.criterion.clone()),
+                (
+                    "CREATEXCRUNCH_INIT_CODE_HASH",
+                    solution.init_code_hash.clone().unwrap_or_default(),
+                ),
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // hand over the input on another thread, so that the timeout also
+        // applies to commands that neither read it nor exit
+        let mut stdin = child.stdin.take().unwrap();
+        let input = format!("{}\n", serde_json::to_string(solution)?);
+        let writer = thread::spawn(move || match stdin.write_all(input.as_bytes()) {
+            // the command need not read its input
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(()),
+        });
+
+        let status = match self.timeout {
+            None => child.wait()?,
+            Some(timeout) => {
+                let deadline = Instant::now() + timeout;
+                loop {
+                    if let Some(status) = child.try_wait()? {
+                        break status;
+                    }
+                    if Instant::now() >= deadline {
+                        child.kill()?;
+                        child.wait()?;
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("{} timed out after {:?}", self.program, timeout),
+                        ));
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+        };
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} exited with {}",
+                self.program, status
+            )));
+        }
+        // anything the command started may still hold on to its input, in
+        // which case the writer is left to finish on its own
+        if writer.is_finished() {
+            writer
+                .join()
+                .map_err(|_| io::Error::other("writing the solution panicked"))??;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hands solutions to another sink on a background thread, so that a slow
+/// sink does not hold up mining. Failures of the sink are logged to standard
+/// error rather than stopping the run.
+pub struct BackgroundSink {
+    sender: Option<Sender<Solution>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BackgroundSink {
+    /// Runs `sink` on a new thread, naming it `name` in the failure log.
+    pub fn new(name: impl Into<String>, mut sink: impl SolutionSink + 'static) -> Self {
+        let name = name.into();
+        let (sender, receiver) = mpsc::channel::<Solution>();
+
+        let worker = thread::spawn(move || {
+            for solution in receiver {
+                if let Err(e) = sink.write(&solution) {
+                    eprintln!("{} failed for salt {}: {}", name, solution.salt, e);
+                }
+            }
+            if let Err(e) = sink.finish() {
+                eprintln!("{} failed to finish: {}", name, e);
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+}
+
+impl SolutionSink for BackgroundSink {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(solution.clone()).ok())
+            .ok_or_else(|| io::Error::other("the background sink has stopped"))
+    }
+
+    /// Waits for the solutions handed over so far to be processed.
+    fn finish(&mut self) -> io::Result<()> {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            worker
+                .join()
+                .map_err(|_| io::Error::other("the background sink panicked"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A command run for every solution, as set with `--on-found`.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    /// Command line, run through the shell.
+    pub command: String,
+    /// Time after which the command is killed.
+    pub timeout: Duration,
+}
+
+impl Hook {
+    /// A sink running the hook in the background.
+    pub fn sink(&self) -> BackgroundSink {
+        BackgroundSink::new(
+            format!("--on-found hook `{}`", self.command),
+            CommandSink::shell(&self.command).timeout(self.timeout),
+        )
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SolutionSink for crate::store::Store {
+    fn write(&mut self, solution: &Solution) -> io::Result<()> {
+        self.insert(solution).map(|_| ()).map_err(io::Error::other)
+    }
+}
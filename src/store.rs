@@ -0,0 +1,167 @@
+//! SQLite store of found salts, shared between runs, machines and people.
+
+use crate::output::Solution;
+use rusqlite::{params, params_from_iter, Connection, Row, ToSql};
+use std::path::Path;
+
+/// Criteria a stored solution must meet to be returned by [`Store::query`].
+/// Unset fields match every solution.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// `create2` or `create3`.
+    pub create_variant: Option<String>,
+    pub factory: Option<String>,
+    pub caller: Option<String>,
+    pub chain_id: Option<u64>,
+    pub init_code_hash: Option<String>,
+    pub min_leading_zeros: Option<u8>,
+    pub min_total_zeros: Option<u8>,
+    /// Maximum number of solutions returned.
+    pub limit: Option<usize>,
+}
+
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        // several miners may write to the same database
+        connection.busy_timeout(std::time::Duration::from_secs(10))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS solutions (
+                salt TEXT NOT NULL,
+                guarded_salt TEXT NOT NULL,
+                address TEXT NOT NULL,
+                checksum_address TEXT NOT NULL,
+                leading_zeros INTEGER NOT NULL,
+                total_zeros INTEGER NOT NULL,
+                criterion TEXT NOT NULL,
+                create_variant TEXT NOT NULL,
+                factory TEXT NOT NULL,
+                caller TEXT,
+                chain_id INTEGER,
+                init_code_hash TEXT,
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                difficulty_bits REAL NOT NULL,
+                calldata TEXT,
+                cast_send TEXT,
+                UNIQUE (salt, address)
+            );
+            CREATE INDEX IF NOT EXISTS solutions_by_score
+                ON solutions (leading_zeros DESC, total_zeros DESC);",
+        )?;
+
+        Ok(Self { connection })
+    }
+
+    /// Stores a solution, unless it has been stored before. Returns whether it
+    /// was new.
+    pub fn insert(&self, solution: &Solution) -> rusqlite::Result<bool> {
+        let inserted = self.connection.execute(
+            "INSERT OR IGNORE INTO solutions VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            params![
+                solution.salt,
+                solution.guarded_salt,
+                solution.address,
+                solution.checksum_address,
+                solution.leading_zeros,
+                solution.total_zeros,
+                solution.criterion,
+                solution.create_variant,
+                solution.factory,
+                solution.caller,
+                solution.chain_id,
+                solution.init_code_hash,
+                solution.timestamp,
+                solution.device,
+                solution.difficulty_bits,
+                solution.calldata,
+                solution.cast_send,
+            ],
+        )?;
+
+        Ok(inserted == 1)
+    }
+
+    /// The stored solutions meeting the filter, best first.
+    pub fn query(&self, filter: &Filter) -> rusqlite::Result<Vec<Solution>> {
+        let mut conditions = vec![];
+        let mut values: Vec<Box<dyn ToSql>> = vec![];
+        // addresses and hashes are compared case-insensitively, so that
+        // checksummed and lowercase forms match
+        let mut condition = |sql: &str, value: Box<dyn ToSql>| {
+            conditions.push(sql.replace('?', &format!("?{}", values.len() + 1)));
+            values.push(value);
+        };
+        if let Some(create_variant) = &filter.create_variant {
+            condition(
+                "create_variant = ?",
+                Box::new(create_variant.to_lowercase()),
+            );
+        }
+        if let Some(factory) = &filter.factory {
+            condition("lower(factory) = lower(?)", Box::new(factory.clone()));
+        }
+        if let Some(caller) = &filter.caller {
+            condition("lower(caller) = lower(?)", Box::new(caller.clone()));
+        }
+        if let Some(chain_id) = filter.chain_id {
+            condition("chain_id = ?", Box::new(chain_id));
+        }
+        if let Some(init_code_hash) = &filter.init_code_hash {
+            condition(
+                "lower(init_code_hash) = lower(?)",
+                Box::new(init_code_hash.clone()),
+            );
+        }
+        if let Some(leading_zeros) = filter.min_leading_zeros {
+            condition("leading_zeros >= ?", Box::new(leading_zeros));
+        }
+        if let Some(total_zeros) = filter.min_total_zeros {
+            condition("total_zeros >= ?", Box::new(total_zeros));
+        }
+
+        let mut sql = "SELECT * FROM solutions".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY leading_zeros DESC, total_zeros DESC, timestamp");
+        if let Some(limit) = filter.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut statement = self.connection.prepare(&sql)?;
+        let solutions = statement
+            .query_map(params_from_iter(values), solution)?
+            .collect();
+        solutions
+    }
+}
+
+fn solution(row: &Row) -> rusqlite::Result<Solution> {
+    Ok(Solution {
+        salt: row.get("salt")?,
+        guarded_salt: row.get("guarded_salt")?,
+        address: row.get("address")?,
+        checksum_address: row.get("checksum_address")?,
+        leading_zeros: row.get("leading_zeros")?,
+        total_zeros: row.get("total_zeros")?,
+        criterion: row.get("criterion")?,
+        create_variant: row.get("create_variant")?,
+        factory: row.get("factory")?,
+        caller: row.get("caller")?,
+        chain_id: row.get("chain_id")?,
+        init_code_hash: row.get("init_code_hash")?,
+        timestamp: row.get("timestamp")?,
+        device: row.get("device")?,
+        difficulty_bits: row.get("difficulty_bits")?,
+        calldata: row.get("calldata")?,
+        cast_send: row.get("cast_send")?,
+    })
+}
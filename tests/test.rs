@@ -1,10 +1,34 @@
-use alloy_primitives::hex::{decode, encode};
+use alloy_primitives::hex::{decode, encode, encode_prefixed};
 use alloy_primitives::FixedBytes;
 use byteorder::{ByteOrder, LittleEndian};
-use createxcrunch::{mk_kernel_src, Config, CreateXVariant, RewardVariant, SaltVariant};
-use itertools::chain;
+use createxcrunch::{
+    annotate_build_log, cache,
+    calldata::Deployment,
+    early_words,
+    estimate::{self, Estimate},
+    export::{self, InitCode},
+    jobs::{self, Backend, JobFile},
+    keccak,
+    metrics::Metrics,
+    mk_generic_kernel_src, mk_kernel_src,
+    output::{self, OutputFormat, Solution},
+    progress::{Progress, RunSummary},
+    reference, results,
+    serve::Service,
+    sink::{CommandSink, FileSink, Hook, SolutionSink},
+    Config, CreateXVariant, KernelPlatform, RewardVariant, SaltVariant,
+};
 use ocl::{Buffer, Context, Device, MemFlags, Platform, ProQue, Program, Queue};
 use rstest::*;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+    time::{Duration, Instant},
+};
 
 #[fixture]
 fn try_nonce(
@@ -24,7 +48,66 @@ fn try_nonce(
         reward,
         // This field will be ignored for tests
         output: "output.txt",
+        loop_count: 1,
+        generic_kernel: false,
+        kernel_cache: None,
+        self_test: false,
+        halt_on_mismatch: true,
+        format: OutputFormat::Text,
+        deployments: Vec::new(),
+        database: None,
+        on_found: None,
+        progress: Progress::Plain,
+        status_interval: Duration::from_secs(10),
+        metrics_addr: None,
+        stop: None,
+        run_summary: None,
     };
+
+    run_kernel(config, nonce).map(|(address, _)| address)
+}
+
+/// A create3 job on the default factory.
+#[fixture]
+fn config(
+    #[default(None)] caller: Option<&'static str>,
+    #[default(None)] chain_id: Option<u64>,
+    #[default(RewardVariant::LeadingZeros { zeros_threshold: 1 })] reward: RewardVariant,
+) -> Config<'static> {
+    Config::new(
+        0,
+        "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+        caller,
+        chain_id,
+        None,
+        reward,
+        "output.txt",
+    )
+    .unwrap()
+}
+
+/// The solution for the salt of the given nonce, whether or not it meets the
+/// criterion of the job.
+#[fixture]
+fn solution(config: Config<'static>, #[default(0)] nonce: u64) -> Solution {
+    let salt = config.salt([0; 4], nonce);
+    Solution::new(
+        &config,
+        salt,
+        reference::address(&config, salt, 0),
+        0,
+        "test",
+        0,
+    )
+}
+
+/// A path in the temporary directory, kept apart from that of other runs.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("createxcrunch-{}-{}", std::process::id(), name))
+}
+
+/// Returns the address found and the index of the init code hash it belongs to.
+fn run_kernel(config: Config, nonce: [u32; 1]) -> ocl::Result<(String, u64)> {
     // set up a platform to use
     let platform = Platform::new(ocl::core::default_platform()?);
 
@@ -36,9 +119,13 @@ fn try_nonce(
         .devices(device)
         .build()?;
 
+    let src = match config.generic_kernel {
+        true => mk_generic_kernel_src(),
+        false => mk_kernel_src(&config),
+    };
     let program = Program::builder()
         .devices(device)
-        .src(mk_kernel_src(&config))
+        .src(src)
         .build(&context)?;
 
     // set up the queue to use
@@ -73,26 +160,41 @@ fn try_nonce(
         .build()?;
 
     // establish a buffer for nonces that result in desired addresses
-    let mut solutions: Vec<u64> = vec![0; 4];
+    let mut solutions: Vec<u64> = vec![0; 5];
     let solutions_buffer = Buffer::builder()
         .queue(ocl_pq.queue().clone())
         .flags(MemFlags::new().write_only())
-        .len(4)
+        .len(5)
         .copy_host_slice(&solutions)
         .build()?;
 
+    // the runtime-parameterized kernel reads the config from a buffer
+    let params = config.params();
+    let params_buffer = Buffer::builder()
+        .queue(ocl_pq.queue().clone())
+        .flags(MemFlags::new().read_only())
+        .len(params.len())
+        .copy_host_slice(&params)
+        .build()?;
+
     // build the kernel and define the type of each buffer
-    let kern = ocl_pq
-        .kernel_builder("hashMessage")
+    let mut kern_builder = ocl_pq.kernel_builder("hashMessage");
+    kern_builder
         .arg_named("message", None::<&Buffer<u8>>)
         .arg_named("nonce", None::<&Buffer<u32>>)
-        .arg_named("solutions", None::<&Buffer<u64>>)
-        .build()?;
+        .arg_named("solutions", None::<&Buffer<u64>>);
+    if config.generic_kernel {
+        kern_builder.arg_named("params", None::<&Buffer<u64>>);
+    }
+    let kern = kern_builder.build()?;
 
     // set each buffer
     kern.set_arg("message", Some(&message_buffer))?;
     kern.set_arg("nonce", Some(&nonce_buffer))?;
     kern.set_arg("solutions", &solutions_buffer)?;
+    if config.generic_kernel {
+        kern.set_arg("params", &params_buffer)?;
+    }
 
     let global_work_size = [1, 1, 1]; // This effectively sets get_global_id(0) to 0 for a single work item
 
@@ -105,23 +207,10 @@ fn try_nonce(
     solutions_buffer.read(&mut solutions).enq()?;
 
     let solution = solutions[0];
-    let solution = solution.to_le_bytes();
 
-    println!("Solution: {:?}", solution);
+    println!("Solution: {:?}", solution.to_le_bytes());
 
-    let mined_salt = chain!(salt, solution[..7].iter().copied());
-
-    let salt: Vec<u8> = match config.salt_variant {
-        SaltVariant::CrosschainSender {
-            chain_id: _,
-            calling_address,
-        } => chain!(calling_address, [1u8], mined_salt).collect(),
-        SaltVariant::Crosschain { chain_id: _ } => chain!([0u8; 20], [1u8], mined_salt).collect(),
-        SaltVariant::Sender { calling_address } => {
-            chain!(calling_address, [0u8], mined_salt).collect()
-        }
-        SaltVariant::Random => chain!(mined_salt, [0u8; 21]).collect(),
-    };
+    let salt = config.salt(salt.0, solution);
 
     println!("Salt: {:?}", salt);
 
@@ -137,7 +226,7 @@ fn try_nonce(
 
     address.insert_str(0, "0x");
 
-    Ok(address)
+    Ok((address, solutions[4]))
 }
 
 #[rstest]
@@ -201,6 +290,20 @@ fn test_create3_random() {
     .unwrap();
 
     assert_eq!("0xbb10c35fdadda68390f7f58b4378ad07826a5471", address);
+
+    let address = try_nonce(
+        SaltVariant::Random,
+        CreateXVariant::Create3,
+        RewardVariant::Matching {
+            pattern: "bb1Xc3XXXXXXXXXXXXXXXXXXXXXXXXXXXX6a5471"
+                .to_owned()
+                .into_boxed_str(),
+        },
+        [87u32; 1],
+    )
+    .unwrap();
+
+    assert_eq!("0xbb10c35fdadda68390f7f58b4378ad07826a5471", address);
 }
 
 #[rstest]
@@ -290,7 +393,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingZeros { zeros_threshold: 1 },
         [126u32; 1],
     )
@@ -300,7 +405,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::TotalZeros { zeros_threshold: 2 },
         [746u32; 1],
     )
@@ -310,7 +417,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingOrTotalZeros {
             leading_zeros_threshold: 1,
             total_zeros_threshold: 2,
@@ -323,7 +432,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingOrTotalZeros {
             leading_zeros_threshold: 5,
             total_zeros_threshold: 2,
@@ -336,7 +447,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingAndTotalZeros {
             leading_zeros_threshold: 1,
             total_zeros_threshold: 2,
@@ -349,7 +462,9 @@ fn test_create2_crosschain() {
 
     let address = try_nonce(
         SaltVariant::Crosschain { chain_id },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::Matching {
             pattern: "bbXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX"
                 .to_owned()
@@ -376,7 +491,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingZeros { zeros_threshold: 1 },
         [343u32; 1],
     )
@@ -389,7 +506,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::TotalZeros { zeros_threshold: 2 },
         [487u32; 1],
     )
@@ -402,7 +521,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingOrTotalZeros {
             leading_zeros_threshold: 1,
             total_zeros_threshold: 2,
@@ -418,7 +539,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingOrTotalZeros {
             leading_zeros_threshold: 5,
             total_zeros_threshold: 2,
@@ -434,7 +557,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::LeadingAndTotalZeros {
             leading_zeros_threshold: 1,
             total_zeros_threshold: 2,
@@ -450,7 +575,9 @@ fn test_create2_crosschain_caller() {
             chain_id,
             calling_address,
         },
-        CreateXVariant::Create2 { init_code_hash },
+        CreateXVariant::Create2 {
+            init_code_hashes: vec![init_code_hash],
+        },
         RewardVariant::Matching {
             pattern: "bbXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX"
                 .to_owned()
@@ -463,6 +590,1321 @@ fn test_create2_crosschain_caller() {
     assert_eq!("0xbbfaecabdd12e01f3a4ce699095ab6dbd1a62b1c", address);
 }
 
+#[rstest]
+fn test_loop_count() {
+    let config = Config {
+        gpu_device: 0,
+        // 0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed
+        factory_address: [
+            186, 94, 208, 153, 99, 61, 59, 49, 62, 77, 95, 123, 220, 19, 5, 211, 194, 139, 165, 237,
+        ],
+        salt_variant: SaltVariant::Random,
+        create_variant: CreateXVariant::Create3,
+        reward: RewardVariant::LeadingZeros { zeros_threshold: 1 },
+        // This field will be ignored for tests
+        output: "output.txt",
+        loop_count: 4,
+        generic_kernel: false,
+        kernel_cache: None,
+        self_test: false,
+        halt_on_mismatch: true,
+        format: OutputFormat::Text,
+        deployments: Vec::new(),
+        database: None,
+        on_found: None,
+        progress: Progress::Plain,
+        status_interval: Duration::from_secs(10),
+        metrics_addr: None,
+        stop: None,
+        run_summary: None,
+    };
+
+    // the work item covers upper nonce words 60 to 63, of which only 61 is a hit
+    let (address, _) = run_kernel(config, [15u32; 1]).unwrap();
+
+    assert_eq!("0x00945498be46467fee556bf2f2f3dcfbd1a6765a", address);
+}
+
+#[rstest]
+fn test_create2_init_code_hashes() {
+    let mut chain_id = [0u8; 32];
+    chain_id[31] = 1;
+
+    let config = Config {
+        gpu_device: 0,
+        // 0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed
+        factory_address: [
+            186, 94, 208, 153, 99, 61, 59, 49, 62, 77, 95, 123, 220, 19, 5, 211, 194, 139, 165, 237,
+        ],
+        salt_variant: SaltVariant::Crosschain { chain_id },
+        create_variant: CreateXVariant::Create2 {
+            init_code_hashes: vec![[0x11u8; 32], [0u8; 32]],
+        },
+        reward: RewardVariant::LeadingZeros { zeros_threshold: 1 },
+        // This field will be ignored for tests
+        output: "output.txt",
+        loop_count: 1,
+        generic_kernel: false,
+        kernel_cache: None,
+        self_test: false,
+        halt_on_mismatch: true,
+        format: OutputFormat::Text,
+        deployments: Vec::new(),
+        database: None,
+        on_found: None,
+        progress: Progress::Plain,
+        status_interval: Duration::from_secs(10),
+        metrics_addr: None,
+        stop: None,
+        run_summary: None,
+    };
+
+    // the same salt as in `test_create2_crosschain`, found for the second hash
+    let (address, index) = run_kernel(config, [126u32; 1]).unwrap();
+
+    assert_eq!("0x006b3047dc49181a8cf360813681ab36246c5b85", address);
+    assert_eq!(1, index);
+}
+
+#[rstest]
+fn test_generic_kernel() {
+    let mut chain_id = [0u8; 32];
+    chain_id[31] = 1;
+
+    let calling_address = string_to_addr_bytes("0x34A50a7A272E86EE30b7A74E36f3f02AF18B1eB5");
+
+    let cases = [
+        (
+            SaltVariant::Random,
+            CreateXVariant::Create3,
+            RewardVariant::LeadingZeros { zeros_threshold: 1 },
+            61,
+            "0x00945498be46467fee556bf2f2f3dcfbd1a6765a",
+        ),
+        (
+            SaltVariant::Sender { calling_address },
+            CreateXVariant::Create3,
+            RewardVariant::LeadingOrTotalZeros {
+                leading_zeros_threshold: 5,
+                total_zeros_threshold: 2,
+            },
+            1579,
+            "0x00ebab0f93b64b8714006f13872816beca04ee88",
+        ),
+        (
+            SaltVariant::Crosschain { chain_id },
+            CreateXVariant::Create2 {
+                init_code_hashes: vec![[0u8; 32]],
+            },
+            RewardVariant::LeadingAndTotalZeros {
+                leading_zeros_threshold: 1,
+                total_zeros_threshold: 2,
+            },
+            2091,
+            "0x00005d7c0b23ffc4036554dea00ecbb6b5f82ba0",
+        ),
+        (
+            SaltVariant::CrosschainSender {
+                chain_id,
+                calling_address,
+            },
+            CreateXVariant::Create2 {
+                init_code_hashes: vec![[0u8; 32]],
+            },
+            RewardVariant::Matching {
+                pattern: "bbXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX"
+                    .to_owned()
+                    .into_boxed_str(),
+            },
+            50,
+            "0xbbfaecabdd12e01f3a4ce699095ab6dbd1a62b1c",
+        ),
+    ];
+
+    for (salt_variant, create_variant, reward, nonce, expected) in cases {
+        let config = Config {
+            gpu_device: 0,
+            // 0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed
+            factory_address: [
+                186, 94, 208, 153, 99, 61, 59, 49, 62, 77, 95, 123, 220, 19, 5, 211, 194, 139, 165,
+                237,
+            ],
+            salt_variant,
+            create_variant,
+            reward,
+            // This field will be ignored for tests
+            output: "output.txt",
+            loop_count: 1,
+            generic_kernel: true,
+            kernel_cache: None,
+            self_test: false,
+            halt_on_mismatch: true,
+            format: OutputFormat::Text,
+            deployments: Vec::new(),
+            database: None,
+            on_found: None,
+            progress: Progress::Plain,
+            status_interval: Duration::from_secs(10),
+            metrics_addr: None,
+            stop: None,
+            run_summary: None,
+        };
+
+        let (address, _) = run_kernel(config, [nonce; 1]).unwrap();
+
+        assert_eq!(expected, address);
+    }
+}
+
+#[rstest]
+fn test_reference() {
+    let mut chain_id = [0u8; 32];
+    chain_id[31] = 1;
+
+    let calling_address = string_to_addr_bytes("0x34A50a7A272E86EE30b7A74E36f3f02AF18B1eB5");
+
+    let cases = [
+        (
+            SaltVariant::Random,
+            CreateXVariant::Create3,
+            87,
+            "0xbb10c35fdadda68390f7f58b4378ad07826a5471",
+        ),
+        (
+            SaltVariant::Sender { calling_address },
+            CreateXVariant::Create3,
+            66,
+            "0x0060e8253a9f9b04d9126b79d77bd022a59e7f9a",
+        ),
+        (
+            SaltVariant::Crosschain { chain_id },
+            CreateXVariant::Create2 {
+                init_code_hashes: vec![[0u8; 32]],
+            },
+            2091,
+            "0x00005d7c0b23ffc4036554dea00ecbb6b5f82ba0",
+        ),
+        (
+            SaltVariant::CrosschainSender {
+                chain_id,
+                calling_address,
+            },
+            CreateXVariant::Create2 {
+                init_code_hashes: vec![[0u8; 32]],
+            },
+            759,
+            "0x004e286d958dffee00dfdccfd438483516fc0c93",
+        ),
+    ];
+
+    for (salt_variant, create_variant, nonce, expected) in cases {
+        let config = Config {
+            gpu_device: 0,
+            // 0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed
+            factory_address: [
+                186, 94, 208, 153, 99, 61, 59, 49, 62, 77, 95, 123, 220, 19, 5, 211, 194, 139, 165,
+                237,
+            ],
+            salt_variant,
+            create_variant,
+            reward: RewardVariant::LeadingZeros { zeros_threshold: 1 },
+            // This field will be ignored for tests
+            output: "output.txt",
+            loop_count: 1,
+            generic_kernel: false,
+            kernel_cache: None,
+            self_test: false,
+            halt_on_mismatch: true,
+            format: OutputFormat::Text,
+            deployments: Vec::new(),
+            database: None,
+            on_found: None,
+            progress: Progress::Plain,
+            status_interval: Duration::from_secs(10),
+            metrics_addr: None,
+            stop: None,
+            run_summary: None,
+        };
+
+        // the nonce as reported by work item 0
+        let salt = config.salt([0; 4], (nonce as u64) << 32);
+        let address = reference::address(&config, salt, 0);
+
+        assert_eq!(expected, format!("0x{}", encode(address)));
+        let pattern = format!("{}{}", &expected[2..4], "X".repeat(38));
+        assert!(reference::meets_criterion(
+            &RewardVariant::Matching {
+                pattern: pattern.into_boxed_str()
+            },
+            &address
+        ));
+    }
+}
+
+#[rstest]
+fn test_calldata() {
+    let salt = [0x11; 32];
+
+    let deployment = Deployment::from_args(Some("0x6001"), None, None, None, None).unwrap();
+    assert_eq!(
+        encode(deployment.calldata(&CreateXVariant::Create3, salt)),
+        [
+            "9c36a286",
+            &"11".repeat(32),
+            &format!("{:064x}", 0x40),
+            &format!("{:064x}", 2),
+            &format!("6001{}", "0".repeat(60)),
+        ]
+        .concat()
+    );
+
+    let deployment =
+        Deployment::from_args(Some("0x6001"), None, Some("0xabcd"), Some("1"), Some("2")).unwrap();
+    assert_eq!(
+        encode(deployment.calldata(&CreateXVariant::Create3, salt)),
+        [
+            "00d84acb",
+            &"11".repeat(32),
+            &format!("{:064x}", 0xa0),
+            &format!("{:064x}", 0xe0),
+            &format!("{:064x}", 1),
+            &format!("{:064x}", 2),
+            &format!("{:064x}", 2),
+            &format!("6001{}", "0".repeat(60)),
+            &format!("{:064x}", 2),
+            &format!("abcd{}", "0".repeat(60)),
+        ]
+        .concat()
+    );
+    assert_eq!(
+        deployment.cast_send([0xba; 20], &CreateXVariant::Create3, salt),
+        format!(
+            "cast send 0xBAbAbabAbabaBABaBAbABabaBAbAbaBaBAbABaBa \"deployCreate3AndInit(bytes32,bytes,bytes,(uint256,uint256))\" 0x{} 0x6001 0xabcd \"(1,2)\" --value 3",
+            "11".repeat(32)
+        )
+    );
+
+    // the init code must be that of the contract being mined for
+    let mut config = Config::new(
+        0,
+        "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+        None,
+        None,
+        Some(&[&encode_prefixed([0x11; 32])]),
+        RewardVariant::LeadingZeros { zeros_threshold: 1 },
+        "output.txt",
+    )
+    .unwrap();
+    assert!(config.set_deployments(vec![deployment]).is_err());
+    let clone = Deployment::from_args(None, Some(&"aa".repeat(20)), None, None, None).unwrap();
+    assert!(config.set_deployments(vec![clone]).is_err());
+}
+
+#[rstest]
+fn test_foundry_script() {
+    let init_code = vec![0x60, 0x80];
+    let init_code_hash = encode_prefixed(alloy_primitives::keccak256(&init_code));
+
+    let config = Config::new(
+        0,
+        "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+        Some("0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"),
+        Some(10),
+        Some(&[&init_code_hash]),
+        RewardVariant::LeadingZeros { zeros_threshold: 1 },
+        "output.txt",
+    )
+    .unwrap();
+    let salt = config.salt([1, 2, 3, 4], 77);
+    let address = reference::address(&config, salt, 0);
+    let solution = Solution::new(&config, salt, address, 0, "test", 0);
+
+    let script =
+        export::foundry_script(&solution, "Deploy", &InitCode::Bytes(init_code), None).unwrap();
+    for expected in [
+        format!("bytes32 internal constant SALT = {};", solution.salt),
+        format!(
+            "address internal constant EXPECTED = {};",
+            solution.checksum_address
+        ),
+        "address internal constant CALLER = 0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A;"
+            .to_string(),
+        "uint256 internal constant CHAIN_ID = 10;".to_string(),
+        format!(
+            "bytes32 internal constant INIT_CODE_HASH = {};",
+            init_code_hash
+        ),
+        "vm.startBroadcast(CALLER);".to_string(),
+        "deployed = CREATEX.deployCreate2(SALT, initCode);".to_string(),
+    ] {
+        assert!(script.contains(&expected), "missing `{}`", expected);
+    }
+
+    // the init code must be that of the contract the salt was mined for
+    assert!(
+        export::foundry_script(&solution, "Deploy", &InitCode::Bytes(vec![0x60]), None).is_err()
+    );
+}
+
+#[rstest]
+fn test_results(
+    #[with(None, None, RewardVariant::TotalZeros { zeros_threshold: 1 })] config: Config<'static>,
+) {
+    // find a salt meeting the criterion, and one that does not
+    let salts = (0..).map(|nonce| config.salt([0; 4], nonce));
+    let good = salts
+        .clone()
+        .find(|&salt| {
+            reference::meets_criterion(&config.reward, &reference::address(&config, salt, 0))
+        })
+        .unwrap();
+    let weak = salts
+        .clone()
+        .find(|&salt| {
+            !reference::meets_criterion(&config.reward, &reference::address(&config, salt, 0))
+        })
+        .unwrap();
+    let line = |salt: [u8; 32], address: [u8; 20]| {
+        format!(
+            "{} => {}\n",
+            encode_prefixed(salt),
+            encode_prefixed(address)
+        )
+    };
+
+    let path = temp_path("results.txt");
+    std::fs::write(
+        &path,
+        [
+            line(good, reference::address(&config, good, 0)),
+            "garbage\n".to_string(),
+            line(good, reference::address(&config, good, 0)),
+            line(weak, reference::address(&config, weak, 0)),
+            // truncated by a killed run
+            line(good, [0x11; 20])[..80].to_string() + "\n",
+            line(good, [0x11; 20]),
+        ]
+        .concat(),
+    )
+    .unwrap();
+    let (entries, failures) = results::read_entries(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(failures.len(), 2);
+    assert!(failures[0].ends_with(":2: unrecognised line"));
+    assert!(failures[1].contains(":5: invalid 20-byte hex value"));
+    assert_eq!(entries.len(), 4);
+    let entries = results::dedupe(entries);
+    assert_eq!(entries.len(), 3);
+
+    assert_eq!(entries[0].verify(&config), Ok(0));
+    // a weak hit and a salt that does not derive the address
+    assert!(entries[1].verify(&config).is_err());
+    assert!(entries[2].verify(&config).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[rstest]
+fn test_store(
+    #[with(Some("0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"), Some(10))] config: Config<'static>,
+) {
+    use createxcrunch::store::{Filter, Store};
+
+    let solution = solution(config, 1);
+    let path = temp_path("store.db");
+    let store = Store::open(&path).unwrap();
+    assert!(store.insert(&solution).unwrap());
+    assert!(!store.insert(&solution).unwrap());
+
+    let query = |filter: Filter| store.query(&filter).unwrap();
+    assert_eq!(
+        query(Filter {
+            create_variant: Some("create3".to_string()),
+            caller: Some("0x88c6c46ebf353a52bdbab708c23d0c81daa8134a".to_string()),
+            chain_id: Some(10),
+            ..Default::default()
+        }),
+        vec![solution.clone()]
+    );
+    assert!(query(Filter {
+        chain_id: Some(1),
+        ..Default::default()
+    })
+    .is_empty());
+    assert!(query(Filter {
+        min_leading_zeros: Some(solution.leading_zeros + 1),
+        ..Default::default()
+    })
+    .is_empty());
+
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[rstest]
+fn test_sinks(#[with(None, Some(1))] config: Config<'static>) {
+    let solutions: Vec<_> = (0..2)
+        .map(|nonce| solution(config.clone(), nonce))
+        .collect();
+
+    let file = temp_path("sink.csv");
+    let piped = temp_path("sink.jsonl");
+    let mut sinks: Vec<Box<dyn SolutionSink>> = vec![
+        Box::new(FileSink::open(&file, OutputFormat::Csv, false).unwrap()),
+        Box::new(CommandSink::new(
+            "sh",
+            vec!["-c".to_string(), format!("cat >> {}", piped.display())],
+        )),
+    ];
+    for solution in &solutions {
+        for sink in sinks.iter_mut() {
+            sink.write(solution).unwrap();
+        }
+    }
+
+    for path in [file, piped] {
+        assert_eq!(output::read_solutions(&path).unwrap(), solutions);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[rstest]
+fn test_on_found_hook(solution: Solution) {
+    let path = temp_path("hook.txt");
+    let mut hook = Hook {
+        command: format!(
+            "echo $CREATEXCRUNCH_SALT $CREATEXCRUNCH_ADDRESS $CREATEXCRUNCH_LEADING_ZEROS > {}",
+            path.display()
+        ),
+        timeout: Duration::from_secs(10),
+    }
+    .sink();
+    hook.write(&solution).unwrap();
+    hook.finish().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(
+            "{} {} {}\n",
+            solution.salt, solution.address, solution.leading_zeros
+        )
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    // slow commands are killed
+    let mut slow = CommandSink::shell("sleep 10").timeout(Duration::from_millis(100));
+    let started = std::time::Instant::now();
+    assert_eq!(
+        slow.write(&solution).unwrap_err().kind(),
+        std::io::ErrorKind::TimedOut
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // even when they never read an input too large for the pipe
+    let large = Solution {
+        calldata: Some(format!("0x{}", "00".repeat(1 << 20))),
+        ..solution.clone()
+    };
+    let started = std::time::Instant::now();
+    assert_eq!(
+        slow.write(&large).unwrap_err().kind(),
+        std::io::ErrorKind::TimedOut
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[rstest]
+fn test_raised_threshold() {
+    let reward = RewardVariant::LeadingOrTotalZeros {
+        leading_zeros_threshold: 3,
+        total_zeros_threshold: 19,
+    };
+    let mut address = [0xff; 20];
+    address[..4].fill(0);
+    assert!(reference::meets_criterion(&reward, &address));
+    assert!(reference::meets_criterion(&reward.raised(1), &address));
+    assert!(!reference::meets_criterion(&reward.raised(2), &address));
+    assert!(matches!(
+        reward.raised(5),
+        RewardVariant::LeadingOrTotalZeros {
+            leading_zeros_threshold: 8,
+            total_zeros_threshold: 20,
+        }
+    ));
+    assert!(reward.raised(1).probability() < reward.probability());
+}
+
+#[rstest]
+fn test_estimate() {
+    // 4 leading zero bytes take 2^32 salts on average
+    let reward = RewardVariant::LeadingZeros { zeros_threshold: 4 };
+    let estimate = Estimate::new(reward.probability(), 2f64.powi(32) / 1e6);
+    assert!((estimate.expected_candidates() - 2f64.powi(32)).abs() < 1.0);
+    assert_eq!(estimate.expected_time().unwrap().as_millis(), 1000);
+    assert_eq!(estimate.percentile(0.5).unwrap().as_millis(), 693);
+    assert!(estimate.feasible());
+
+    // a salt meets the criterion for either of two hashes about twice as often
+    let p = estimate::per_salt(reward.probability(), 2);
+    assert!((p / reward.probability() - 2.0).abs() < 1e-6);
+
+    // leading and total zeros are counted exactly: all of the first 2 bytes and
+    // at least 1 of the other 18
+    let both = RewardVariant::LeadingAndTotalZeros {
+        leading_zeros_threshold: 2,
+        total_zeros_threshold: 3,
+    };
+    let expected = 256f64.powi(-2) * (1.0 - (255.0f64 / 256.0).powi(18));
+    assert!((both.probability() / expected - 1.0).abs() < 1e-9);
+
+    let hard = RewardVariant::LeadingZeros {
+        zeros_threshold: 12,
+    };
+    assert!(!Estimate::new(hard.probability(), 1000.0).feasible());
+}
+
+#[rstest]
+fn test_metrics(
+    #[with(Some("0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"))] config: Config<'static>,
+) {
+    let solution = solution(config.clone(), 0);
+
+    let metrics = Metrics::serve("127.0.0.1:0".parse().unwrap(), &config, "test").unwrap();
+    metrics.dispatch(1000, Duration::from_millis(500));
+    metrics.dispatch(1000, Duration::from_millis(250));
+    metrics.solution(&solution);
+
+    let mut stream = std::net::TcpStream::connect(metrics.local_addr().unwrap()).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let labels = "device=\"test\",variant=\"create3\",\
+                  factory=\"0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed\",\
+                  caller=\"0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A\"";
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains(&format!("createxcrunch_hashes_total{{{labels}}} 2000\n")));
+    assert!(response.contains(&format!("createxcrunch_hash_rate{{{labels}}} 4000\n")));
+    assert!(response.contains(&format!(
+        "createxcrunch_dispatch_duration_seconds_count{{{labels}}} 2\n"
+    )));
+    assert!(response.contains(&format!(
+        "createxcrunch_solutions_total{{{labels},criterion=\"with 1 leading zero byte(s)\"}} 1\n"
+    )));
+    assert_eq!(response.split("\r\n\r\n").nth(1).unwrap(), metrics.render());
+}
+
+#[rstest]
+fn test_run_summary(
+    #[with(None, None, RewardVariant::TotalZeros { zeros_threshold: 1 })] config: Config<'static>,
+) {
+    let solution = |leading_zeros: usize, total_zeros: usize| {
+        let mut address = [0xff; 20];
+        address[..leading_zeros].fill(0);
+        address[19 - (total_zeros - leading_zeros)..19].fill(0);
+        Solution::new(&config, [0; 32], address, 0, "test", 0)
+    };
+
+    let mut summary = RunSummary {
+        runtime: Duration::from_secs(2),
+        hashes: 4_000_000,
+        ..RunSummary::default()
+    };
+    for (leading_zeros, total_zeros) in [(0, 2), (1, 1), (1, 3), (0, 2)] {
+        summary.add(&solution(leading_zeros, total_zeros));
+    }
+
+    assert_eq!(summary.found(), 4);
+    assert_eq!(summary.by_score[&(0, 2)], 2);
+    assert_eq!(summary.rate(), 2.0);
+    let best = summary.best.unwrap();
+    assert_eq!((best.leading_zeros, best.total_zeros), (1, 3));
+}
+
+#[rstest]
+fn test_serve() {
+    let service = Service::start("127.0.0.1:0".parse().unwrap(), &[Backend::Cpu], 1, None).unwrap();
+    let addr = service.local_addr().unwrap();
+    let wait_for = |id: u64, state: &str| {
+        let started = Instant::now();
+        loop {
+            let (_, job) = http(addr, "GET", &format!("/jobs/{}", id), "");
+            let job: serde_json::Value = serde_json::from_str(&job).unwrap();
+            if job["state"] == state {
+                return job;
+            }
+            assert!(started.elapsed() < Duration::from_secs(60), "{}", job);
+            thread::sleep(Duration::from_millis(50));
+        }
+    };
+
+    for invalid in [
+        r#"{"zeros":1}"#,
+        r#"{"leading":1,"factory":"0xnothex"}"#,
+        r#"{"leading":1,"caller":"0x1234"}"#,
+        r#"{"total":1,"init_code_hashes":["0x12"]}"#,
+        r#"{"leading":1,"loop_count":0}"#,
+    ] {
+        let (status, _) = http(addr, "POST", "/jobs", invalid);
+        assert_eq!(status, 400, "{}", invalid);
+    }
+
+    let (status, job) = http(
+        addr,
+        "POST",
+        "/jobs",
+        r#"{"caller":"0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A","leading":1,"max_solutions":3}"#,
+    );
+    assert_eq!(status, 201);
+    let job: serde_json::Value = serde_json::from_str(&job).unwrap();
+    let id = job["id"].as_u64().unwrap();
+    let job = wait_for(id, "done");
+    assert_eq!(job["device"], "cpu");
+    assert_eq!(job["found"], 3);
+
+    let config = Config::new(
+        0,
+        "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+        Some("0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"),
+        None,
+        None,
+        RewardVariant::LeadingZeros { zeros_threshold: 1 },
+        "",
+    )
+    .unwrap();
+    let (status, solutions) = http(addr, "GET", &format!("/jobs/{}/solutions", id), "");
+    assert_eq!(status, 200);
+    let solutions: Vec<Solution> = serde_json::from_str(&solutions).unwrap();
+    assert_eq!(solutions.len(), 3);
+    for solution in &solutions {
+        let salt = <[u8; 32]>::try_from(decode(&solution.salt).unwrap()).unwrap();
+        let address = reference::address(&config, salt, 0);
+        assert_eq!(solution.address, encode_prefixed(address));
+        assert_eq!(address[0], 0);
+    }
+
+    let (status, streamed) = http(addr, "GET", &format!("/jobs/{}/solutions?stream", id), "");
+    assert_eq!(status, 200);
+    let streamed = streamed
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<Solution>>();
+    assert_eq!(streamed, solutions);
+
+    // never found, so it runs until cancelled
+    let (_, job) = http(addr, "POST", "/jobs", r#"{"leading":20}"#);
+    let job: serde_json::Value = serde_json::from_str(&job).unwrap();
+    let id = job["id"].as_u64().unwrap();
+    wait_for(id, "running");
+    let (status, _) = http(addr, "DELETE", &format!("/jobs/{}", id), "");
+    assert_eq!(status, 200);
+    let job = wait_for(id, "cancelled");
+    assert!(job["hashes"].as_u64().unwrap() > 0);
+
+    let (status, jobs) = http(addr, "GET", "/jobs", "");
+    assert_eq!(status, 200);
+    assert_eq!(
+        serde_json::from_str::<Vec<serde_json::Value>>(&jobs)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(http(addr, "GET", "/jobs/7", "").0, 404);
+
+    service.shutdown();
+}
+
+#[rstest]
+fn test_job_file() {
+    let path = temp_path("jobs.toml");
+    let token = temp_path("jobs-token.jsonl");
+    let vault = temp_path("jobs-vault.txt");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+schedule = "round-robin"
+slice = 1
+
+[[jobs]]
+name = "token"
+caller = "0x88c6C46EBf353A52Bdbab708c23D0c81dAA8134A"
+crosschain = 1
+leading = 1
+max_solutions = 2
+output = "{}"
+format = "jsonl"
+
+[[jobs]]
+variant = "create2"
+init_code_hashes = ["0x{}"]
+total = 1
+max_time = 2
+output = "{}"
+"#,
+            token.display(),
+            "ab".repeat(32),
+            vault.display()
+        ),
+    )
+    .unwrap();
+
+    let file = JobFile::read(&path).unwrap();
+    assert_eq!(file.label(0), "token");
+    assert_eq!(file.label(1), "job 2");
+    let summaries = jobs::run(
+        &file,
+        Backend::Cpu,
+        Progress::None,
+        &Arc::new(AtomicBool::new(false)),
+    )
+    .unwrap();
+    assert!(summaries
+        .iter()
+        .zip(&file.jobs)
+        .all(|(summary, spec)| spec.done(summary)));
+    assert!(summaries[0].found() >= 2);
+    assert!(summaries[1].runtime >= Duration::from_secs(2));
+
+    let config = file.jobs[0].config(0, "").unwrap();
+    let solutions = output::read_solutions(&token).unwrap();
+    assert_eq!(solutions.len() as u64, summaries[0].found());
+    for solution in &solutions {
+        assert_eq!(solution.chain_id, Some(1));
+        let salt = <[u8; 32]>::try_from(decode(&solution.salt).unwrap()).unwrap();
+        assert_eq!(
+            solution.address,
+            encode_prefixed(reference::address(&config, salt, 0))
+        );
+    }
+    let found = std::fs::read_to_string(&vault).unwrap().lines().count();
+    assert_eq!(found as u64, summaries[1].found());
+
+    // every job is checked before any runs
+    let mut invalid = file.clone();
+    invalid.jobs[1].init_code_hashes.clear();
+    assert!(invalid.validate().unwrap_err().starts_with("job 2:"));
+    for loop_count in [0, 4097, 1 << 25] {
+        let mut invalid = file.clone();
+        invalid.jobs[0].loop_count = Some(loop_count);
+        assert!(invalid.validate().unwrap_err().starts_with("token:"));
+    }
+    let mut invalid = file.clone();
+    invalid.jobs[1].init_code_hashes = vec!["0xnothex".to_string()];
+    assert!(invalid.validate().unwrap_err().starts_with("job 2:"));
+    let mut endless = file.clone();
+    endless.schedule = jobs::Schedule::InOrder;
+    endless.jobs[0].max_solutions = None;
+    assert!(endless.validate().is_err());
+    endless.jobs.swap(0, 1);
+    assert!(endless.validate().is_ok());
+
+    for path in [&path, &token, &vault] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[rstest]
+fn test_fair_share() {
+    let path = temp_path("fair.json");
+    let light = temp_path("fair-light.txt");
+    let heavy = temp_path("fair-heavy.txt");
+    // never found, so both run until their time is up
+    std::fs::write(
+        &path,
+        serde_json::json!({
+            "schedule": "fair",
+            "jobs": [
+                {"leading": 20, "max_time": 4, "output": light},
+                {"leading": 20, "max_time": 4, "output": heavy, "weight": 3}
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let file = JobFile::read(&path).unwrap();
+    let summaries = jobs::run(
+        &file,
+        Backend::Cpu,
+        Progress::None,
+        &Arc::new(AtomicBool::new(false)),
+    )
+    .unwrap();
+    assert!(summaries[0].hashes > 0);
+    assert!(summaries[1].hashes >= 2 * summaries[0].hashes);
+    // shared rather than taking turns
+    assert!(summaries[0].runtime < Duration::from_secs(6));
+    assert!(summaries[1].runtime < Duration::from_secs(6));
+
+    let mut unweighted = file.clone();
+    unweighted.jobs[1].weight = Some(0);
+    assert!(unweighted.validate().unwrap_err().starts_with("job 2:"));
+
+    for path in [&path, &light, &heavy] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[rstest]
+fn test_kernel_cache() {
+    let key = cache::cache_key("gfx1100", "3614.0", "__kernel void f() {}", "-D PLATFORM=2");
+    assert_eq!(key.len(), 64);
+    assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(
+        key,
+        cache::cache_key("gfx1100", "3614.0", "__kernel void f() {}", "-D PLATFORM=2")
+    );
+    // anything that affects the binary gives another key
+    for other in [
+        cache::cache_key("gfx1030", "3614.0", "__kernel void f() {}", "-D PLATFORM=2"),
+        cache::cache_key("gfx1100", "3615.0", "__kernel void f() {}", "-D PLATFORM=2"),
+        cache::cache_key("gfx1100", "3614.0", "__kernel void g() {}", "-D PLATFORM=2"),
+        cache::cache_key("gfx1100", "3614.0", "__kernel void f() {}", "-D PLATFORM=0"),
+        // the parts are kept apart
+        cache::cache_key("gfx110", "03614.0", "__kernel void f() {}", "-D PLATFORM=2"),
+    ] {
+        assert_ne!(key, other);
+    }
+
+    let dir = temp_path("cache");
+    assert_eq!(cache::load(&dir, &key), None);
+    cache::store(&dir, &key, b"first").unwrap();
+    assert_eq!(cache::load(&dir, &key).as_deref(), Some(&b"first"[..]));
+    cache::store(&dir, &key, b"second").unwrap();
+    assert_eq!(cache::load(&dir, &key).as_deref(), Some(&b"second"[..]));
+    // nothing is left behind but the binary
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[rstest]
+fn test_final_round() {
+    let rewards = [
+        RewardVariant::LeadingZeros { zeros_threshold: 5 },
+        RewardVariant::TotalZeros { zeros_threshold: 3 },
+        RewardVariant::LeadingAndTotalZeros {
+            leading_zeros_threshold: 9,
+            total_zeros_threshold: 10,
+        },
+        RewardVariant::LeadingOrTotalZeros {
+            leading_zeros_threshold: 2,
+            total_zeros_threshold: 4,
+        },
+        RewardVariant::Matching {
+            pattern: "XXXXXXXXXXXXba5edXXXXXXXXXXXXXXXXXXXXX0f".into(),
+        },
+    ];
+    for reward in rewards {
+        let early = early_words(&reward);
+        let src = keccak::final_round(&early);
+
+        // the preimages of CREATE2 and CREATE3 addresses, and then some
+        for len in [23, 85, 1, 135] {
+            let input = (0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+            let hash = alloy_primitives::keccak256(&input);
+            let expected =
+                |word: usize| u32::from_le_bytes(hash[12 + 4 * word..][..4].try_into().unwrap());
+
+            let mut sponge = [0u8; 200];
+            sponge[..len].copy_from_slice(&input);
+            sponge[len] ^= 0x01;
+            sponge[135] ^= 0x80;
+            let mut a: [u64; 25] = std::array::from_fn(|i| {
+                u64::from_le_bytes(sponge[8 * i..][..8].try_into().unwrap())
+            });
+            for rc in KECCAK_RC.iter().take(23) {
+                keccak_round(&mut a, *rc);
+            }
+            let word =
+                |a: &[u64; 25], word: usize| (a[(word + 3) / 2] >> (32 * ((word + 3) % 2))) as u32;
+
+            let mut vars = HashMap::new();
+            eval_macro(&src, "FINAL_ROUND_EARLY", &mut a, &mut vars);
+            for &w in &early {
+                assert_eq!(
+                    word(&a, w),
+                    expected(w),
+                    "{}, early word {}",
+                    reward.description(),
+                    w
+                );
+            }
+            eval_macro(&src, "FINAL_ROUND_REST", &mut a, &mut vars);
+            for w in 0..5 {
+                assert_eq!(
+                    word(&a, w),
+                    expected(w),
+                    "{}, word {}",
+                    reward.description(),
+                    w
+                );
+            }
+        }
+    }
+}
+
+/// Round constants of keccak-f[1600].
+const KECCAK_RC: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+/// One round of keccak-f[1600], with the lanes indexed by `x + 5 * y`.
+fn keccak_round(a: &mut [u64; 25], rc: u64) {
+    const RHO: [u32; 25] = [
+        0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56,
+        14,
+    ];
+    let c: [u64; 5] = std::array::from_fn(|x| (0..5).fold(0, |c, y| c ^ a[x + 5 * y]));
+    let d: [u64; 5] = std::array::from_fn(|x| c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1));
+    let mut b = [0u64; 25];
+    for y in 0..5 {
+        for x in 0..5 {
+            b[y + 5 * ((2 * x + 3 * y) % 5)] = (a[x + 5 * y] ^ d[x]).rotate_left(RHO[x + 5 * y]);
+        }
+    }
+    for y in 0..5 {
+        for x in 0..5 {
+            a[x + 5 * y] = b[x + 5 * y] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+        }
+    }
+    a[0] ^= rc;
+}
+
+/// Runs the statements of the macro `name` in the generated source on the
+/// host, with `a` as the sponge state and `vars` holding its temporaries.
+fn eval_macro(src: &str, name: &str, a: &mut [u64; 25], vars: &mut HashMap<String, u64>) {
+    let body = src
+        .lines()
+        .skip_while(|line| !line.starts_with(&format!("#define {name}(a)")))
+        .skip(1)
+        .take_while(|line| !line.is_empty());
+    for line in body {
+        let statement = line
+            .trim()
+            .trim_end_matches('\\')
+            .trim()
+            .trim_end_matches(';');
+        let (target, expr) = statement.split_once(" = ").unwrap();
+        let value = Expr::new(expr, a, vars).eval();
+        match target.strip_prefix("ulong ") {
+            Some(var) => {
+                vars.insert(var.to_string(), value);
+            }
+            None => {
+                // ((uint *)(a))[i] = ...
+                let index: usize = target
+                    .trim_start_matches("((uint *)(a))[")
+                    .trim_end_matches(']')
+                    .parse()
+                    .unwrap();
+                let shift = 32 * (index % 2);
+                a[index / 2] =
+                    a[index / 2] & !(0xffffffff << shift) | (value & 0xffffffff) << shift;
+            }
+        }
+    }
+}
+
+/// Evaluates the OpenCL expressions the final round is made of.
+struct Expr<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    a: &'a [u64; 25],
+    vars: &'a HashMap<String, u64>,
+}
+
+impl<'a> Expr<'a> {
+    fn new(expr: &str, a: &'a [u64; 25], vars: &'a HashMap<String, u64>) -> Self {
+        let mut tokens = vec![];
+        let mut chars = expr.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' => (),
+                '>' => {
+                    chars.next();
+                    tokens.push(">>".to_string());
+                }
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    let mut token = c.to_string();
+                    while let Some(&c) = chars
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    {
+                        token.push(c);
+                        chars.next();
+                    }
+                    tokens.push(token);
+                }
+                c => tokens.push(c.to_string()),
+            }
+        }
+        Self {
+            tokens,
+            pos: 0,
+            a,
+            vars,
+        }
+    }
+
+    fn eval(mut self) -> u64 {
+        let value = self.xor();
+        assert_eq!(self.pos, self.tokens.len(), "{:?}", self.tokens);
+        value
+    }
+
+    fn peek(&self, offset: usize) -> &str {
+        self.tokens
+            .get(self.pos + offset)
+            .map_or("", String::as_str)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.peek(0) == token;
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect(&mut self, token: &str) {
+        assert!(self.eat(token), "expected {} in {:?}", token, self.tokens);
+    }
+
+    fn xor(&mut self) -> u64 {
+        let mut value = self.and();
+        while self.eat("^") {
+            value ^= self.and();
+        }
+        value
+    }
+
+    fn and(&mut self) -> u64 {
+        let mut value = self.shift();
+        while self.eat("&") {
+            value &= self.shift();
+        }
+        value
+    }
+
+    fn shift(&mut self) -> u64 {
+        let mut value = self.unary();
+        while self.eat(">>") {
+            value >>= self.number();
+        }
+        value
+    }
+
+    fn unary(&mut self) -> u64 {
+        if self.eat("~") {
+            return !self.unary();
+        }
+        if self.peek(0) == "(" && self.peek(1) == "uint" {
+            self.pos += 3;
+            return self.unary() & 0xffffffff;
+        }
+        if self.eat("(") {
+            let value = self.xor();
+            self.expect(")");
+            return value;
+        }
+        if self.eat("ROL") {
+            self.expect("(");
+            let value = self.xor();
+            self.expect(",");
+            let n = self.number();
+            self.expect(")");
+            return value.rotate_left(n as u32);
+        }
+        if self.eat("a") {
+            self.expect("[");
+            let index = self.number();
+            self.expect("]");
+            return self.a[index as usize];
+        }
+        match self.vars.get(self.peek(0)) {
+            Some(&value) => {
+                self.pos += 1;
+                value
+            }
+            None => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> u64 {
+        let token = self.peek(0).trim_end_matches(['u', 'U', 'L']).to_string();
+        self.pos += 1;
+        match token.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).unwrap(),
+            None => token.parse().unwrap(),
+        }
+    }
+}
+
+#[rstest]
+fn test_annotate_build_log() {
+    let src = "#define A 1\n__kernel void f() {\n  x = A;\n}\n";
+    let log = "<source>:3:3: error: use of undeclared identifier 'x'\n\
+               1 error generated.\n\
+               <source>:1:9: warning: macro redefined\n\
+               <source>:99:1: error: past the end";
+    assert_eq!(
+        annotate_build_log(src, log),
+        "<source>:3:3: error: use of undeclared identifier 'x'\n\
+         \x20     1 | #define A 1\n\
+         \x20     2 | __kernel void f() {\n\
+         \x20     3 |   x = A;\n\
+         \x20       |   ^\n\
+         1 error generated.\n\
+         <source>:1:9: warning: macro redefined\n\
+         \x20     1 | #define A 1\n\
+         \x20       |         ^\n\
+         <source>:99:1: error: past the end\n"
+    );
+}
+
+#[rstest]
+fn test_config_summary() {
+    let caller = "88c6c46ebf353a52bdbab708c23d0c81daa8134a";
+    let summary = |caller: Option<&str>, chain_id: Option<u64>| {
+        Config::new(
+            0,
+            "0xba5Ed099633D3B313e4D5F7bdc1305d3c28ba5Ed",
+            caller,
+            chain_id,
+            None,
+            RewardVariant::LeadingZeros { zeros_threshold: 2 },
+            "",
+        )
+        .unwrap()
+        .summary()
+    };
+    let mined = format!("{}{}", "M".repeat(8), "N".repeat(14));
+
+    for (summary, protection, chain_id, layout) in [
+        (
+            summary(None, None),
+            "unprotected",
+            "none",
+            format!("{}{}", mined, "00".repeat(21)),
+        ),
+        (
+            summary(Some(caller), None),
+            "permissioned",
+            "none",
+            format!("{}00{}", caller, mined),
+        ),
+        (
+            summary(None, Some(10)),
+            "crosschain protected",
+            "10",
+            format!("{}01{}", "00".repeat(20), mined),
+        ),
+        (
+            summary(Some(caller), Some(10)),
+            "permissioned, crosschain protected",
+            "10",
+            format!("{}01{}", caller, mined),
+        ),
+    ] {
+        assert!(summary.contains("factory:          0xba5ed099633d3b313e4d5f7bdc1305d3c28ba5ed\n"));
+        assert!(summary.contains(&format!("salt protection:  {}\n", protection)));
+        assert!(summary.contains(&format!("chain ID:         {}\n", chain_id)));
+        assert!(
+            summary.contains(&format!("salt layout:      0x{}\n", layout)),
+            "{}",
+            summary
+        );
+        assert_eq!(layout.len(), 64);
+    }
+}
+
+#[rstest]
+fn test_kernel_platform() {
+    for (vendor, extensions, platform) in [
+        (
+            "Advanced Micro Devices, Inc.",
+            "cl_khr_fp64 cl_amd_media_ops cl_amd_media_ops2",
+            KernelPlatform::Amd,
+        ),
+        ("AMD", "cl_amd_media_ops", KernelPlatform::Amd),
+        // without the extension, AMD devices only get the portable code
+        (
+            "Advanced Micro Devices, Inc.",
+            "cl_khr_fp64 cl_amd_media_ops2",
+            KernelPlatform::Unknown,
+        ),
+        (
+            "NVIDIA Corporation",
+            "cl_khr_fp64 cl_nv_pragma_unroll",
+            KernelPlatform::Nvidia,
+        ),
+        ("nvidia", "", KernelPlatform::Nvidia),
+        (
+            "Intel(R) Corporation",
+            "cl_khr_fp64",
+            KernelPlatform::Unknown,
+        ),
+        (
+            "Apple",
+            "cl_APPLE_SetMemObjectDestructor",
+            KernelPlatform::Unknown,
+        ),
+        ("", "", KernelPlatform::Unknown),
+    ] {
+        assert_eq!(
+            KernelPlatform::for_device(vendor, extensions),
+            platform,
+            "{} with {}",
+            vendor,
+            extensions
+        );
+    }
+    assert_eq!(KernelPlatform::Amd.build_options(), "-D PLATFORM=2");
+}
+
+/// Makes an HTTP/1.0 request, returning the status and body of the response.
+fn http(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
 fn string_to_addr_bytes(s: &str) -> [u8; 20] {
     let mut addr = [0u8; 20];
     let s = s.trim_start_matches("0x");